@@ -0,0 +1,302 @@
+use std::{
+    sync::{Arc, mpsc},
+    thread, time,
+};
+
+use winit::{
+    application::ApplicationHandler,
+    dpi::PhysicalSize,
+    event::{ElementState, KeyEvent, WindowEvent},
+    event_loop::{ActiveEventLoop, EventLoop},
+    keyboard::{KeyCode, PhysicalKey},
+    window::{Window, WindowId},
+};
+
+use crate::constants;
+use crate::game::character::CharacterMovement;
+use crate::game::game::{Game, GameCommand, GameExports, GameForInit, GameState};
+use crate::game::timestep::FixedTimestep;
+use crate::graphics::graphics::Graphics;
+use crate::input::InputState;
+use crate::triple_buffer::{Reader, triple_buffer};
+
+// Window events the draw thread, which owns the graphics and the input state, has to handle
+#[allow(clippy::upper_case_acronyms)]
+enum FrontendEvent {
+    KEY(KeyCode, ElementState, bool),
+    RESIZE(PhysicalSize<u32>),
+}
+
+// The game thread owns the game and the draw thread owns the graphics, they only talk
+// through the game command channel and the snapshot triple buffer so neither waits on the other
+struct Aiomad {
+    game_for_init: Option<GameForInit>,
+    snapshots: Option<Reader<GameExports>>,
+    frontend_sender: Option<mpsc::Sender<FrontendEvent>>,
+    draw_thread: Option<thread::JoinHandle<Graphics>>,
+    game_sender: mpsc::Sender<GameCommand>,
+    game_thread: Option<thread::JoinHandle<()>>,
+}
+
+/// Opens the window and runs the game, blocks until the window is closed. The game is then
+/// written to `save_path` if there is one.
+pub fn run(mut game: Game, save_path: Option<String>) {
+    let event_loop = EventLoop::new().unwrap();
+    event_loop.set_control_flow(winit::event_loop::ControlFlow::Poll);
+    let (mut snapshot_writer, snapshot_reader) = triple_buffer(game.get_exports());
+    let (game_sender, game_receiver) = mpsc::channel::<GameCommand>();
+
+    let mut aiomad = Aiomad {
+        game_for_init: Some(game.get_for_init()),
+        snapshots: Some(snapshot_reader),
+        frontend_sender: None,
+        draw_thread: None,
+        game_sender,
+        game_thread: None,
+    };
+
+    aiomad.game_thread = Some(thread::spawn(move || {
+        let mut running = true;
+        let mut character_movement = CharacterMovement::new();
+        let mut timestep = FixedTimestep::new(
+            time::Duration::from_millis(constants::THICK_RATE_MILLIS),
+            time::Duration::from_millis(constants::MAX_FRAME_TIME_MILLIS),
+            constants::MAX_TICKS_PER_FRAME,
+        );
+        while running {
+            loop {
+                match game_receiver.try_recv() {
+                    Ok(GameCommand::MOVE(movement)) => character_movement = movement,
+                    Ok(GameCommand::TERMINATE) | Err(mpsc::TryRecvError::Disconnected) => {
+                        game.terminate();
+                        break;
+                    }
+                    Err(mpsc::TryRecvError::Empty) => break,
+                }
+            }
+
+            let ticks = timestep.advance();
+            for _ in 0..ticks {
+                if game.update(character_movement, timestep.dt()) == GameState::DEAD {
+                    running = false;
+                    break;
+                };
+            }
+            if ticks > 0 {
+                snapshot_writer.publish(game.get_exports());
+            }
+
+            thread::sleep(timestep.until_next_tick());
+        }
+
+        if let Some(path) = save_path {
+            match game.save(&path) {
+                Ok(()) => eprintln!("Game saved to {}", path),
+                Err(error) => eprintln!("Saving to {} failed: {}", path, error),
+            }
+        }
+    }));
+
+    event_loop.run_app(&mut aiomad).unwrap();
+}
+
+impl Aiomad {
+    // Stops the draw thread first, as it renders to the window and sends commands to the
+    // game, then the game thread. Graphics come back from the draw thread to be dropped
+    // here, before the event loop tears the window down
+    fn terminate(&mut self, event_loop: &ActiveEventLoop) {
+        eprintln!("Terminating...");
+
+        self.frontend_sender.take();
+        if let Some(thread) = self.draw_thread.take() {
+            let graphics = thread.join().unwrap();
+            eprintln!("Thread draw_thread catched");
+            drop(graphics);
+            eprintln!("Graphics dropped to help Mr. SegFault");
+        }
+        let _ = self.game_sender.send(GameCommand::TERMINATE);
+        if let Some(thread) = self.game_thread.take() {
+            thread.join().unwrap();
+            eprintln!("Thread game_thread catched");
+        }
+        eprintln!("Exit called from thread: {:?}", std::thread::current().id());
+        event_loop.exit();
+    }
+
+    fn frontend_loop(&mut self, mut graphics: Graphics) {
+        let mut snapshots = self.snapshots.take().unwrap();
+        let game_sender = self.game_sender.clone();
+        let (frontend_sender, frontend_receiver) = mpsc::channel();
+        self.frontend_sender = Some(frontend_sender);
+
+        self.draw_thread = Some(thread::spawn(move || {
+            let mut running = true;
+            let mut input_state = InputState::new();
+            let mut delta_time = time::Instant::now();
+            let mut current_exports = snapshots.latest().clone();
+            let mut previous_exports = current_exports.clone();
+            while running {
+                loop {
+                    match frontend_receiver.try_recv() {
+                        Ok(FrontendEvent::KEY(key, state, repeat)) => {
+                            input_state.update_key(key, state, repeat)
+                        }
+                        Ok(FrontendEvent::RESIZE(size)) => graphics.resize(size),
+                        Err(mpsc::TryRecvError::Empty) => break,
+                        Err(mpsc::TryRecvError::Disconnected) => {
+                            running = false;
+                            break;
+                        }
+                    }
+                }
+
+                let latest_exports = snapshots.latest();
+                if latest_exports.tick != current_exports.tick {
+                    previous_exports =
+                        std::mem::replace(&mut current_exports, latest_exports.clone());
+                }
+                let frame_exports = Self::interpolate_exports(
+                    &previous_exports,
+                    &current_exports,
+                    time::Instant::now(),
+                );
+                Self::redraw(&frame_exports, &mut graphics);
+
+                let (state, camera, capture) = (
+                    &mut graphics.state,
+                    &mut graphics.camera,
+                    &mut graphics.capture,
+                );
+                input_state.do_your_job(state, camera, capture, &mut running);
+                let _ = game_sender.send(GameCommand::MOVE(input_state.get_character_movement()));
+
+                if delta_time.elapsed()
+                    <= time::Duration::from_millis(constants::MAX_FRAMERATE_MILLIS)
+                {
+                    thread::sleep(
+                        time::Duration::from_millis(constants::MAX_FRAMERATE_MILLIS)
+                            - delta_time.elapsed(),
+                    );
+                }
+                delta_time = time::Instant::now();
+            }
+            graphics
+        }));
+    }
+
+    // Renders one snapshot behind the game: moves from the previous snapshot to the current
+    // one over the time the ticks between them took
+    fn interpolate_exports(
+        previous: &GameExports,
+        current: &GameExports,
+        now: time::Instant,
+    ) -> GameExports {
+        let ticks = current.tick.saturating_sub(previous.tick).max(1) as u32;
+        let span = time::Duration::from_millis(constants::THICK_RATE_MILLIS) * ticks;
+        let elapsed = now.saturating_duration_since(current.timestamp);
+        let alpha = elapsed.as_secs_f32() / span.as_secs_f32();
+        GameExports::interpolate(previous, current, alpha)
+    }
+
+    fn redraw(positions: &GameExports, graphics: &mut Graphics) {
+        match graphics.update_all(positions) {
+            Ok(_) => {}
+            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => graphics.reconfigure(),
+            Err(wgpu::SurfaceError::OutOfMemory | wgpu::SurfaceError::Other) => {
+                eprintln!("OutOfMemory");
+            }
+            Err(wgpu::SurfaceError::Timeout) => {
+                eprintln!("Surface timeout")
+            }
+        };
+    }
+}
+
+impl ApplicationHandler for Aiomad {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        let window = Arc::new(
+            event_loop
+                .create_window(Window::default_attributes().with_title("Airomad"))
+                .unwrap(),
+        );
+        let Some(game_for_init) = self.game_for_init.take() else {
+            return;
+        };
+        let graphics = pollster::block_on(Graphics::new(window.clone(), game_for_init));
+
+        self.frontend_loop(graphics);
+    }
+
+    fn window_event(
+        &mut self,
+        event_loop: &ActiveEventLoop,
+        _window_id: WindowId,
+        event: WindowEvent,
+    ) {
+        match event {
+            WindowEvent::CloseRequested => self.terminate(event_loop),
+
+            // Frames are drawn by the draw thread at its own pace
+            WindowEvent::RedrawRequested => {}
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(key),
+                        state,
+                        repeat,
+                        ..
+                    },
+                ..
+            } => {
+                if let Some(frontend_sender) = &self.frontend_sender {
+                    let _ = frontend_sender.send(FrontendEvent::KEY(key, state, repeat));
+                }
+                if key == winit::keyboard::KeyCode::Escape {
+                    self.terminate(event_loop);
+                }
+            }
+
+            WindowEvent::Resized(size) => {
+                if let Some(frontend_sender) = &self.frontend_sender {
+                    let _ = frontend_sender.send(FrontendEvent::RESIZE(size));
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exports(tick: u64, timestamp: time::Instant, character: [f32; 3]) -> GameExports {
+        GameExports {
+            tick,
+            timestamp,
+            character,
+            chunks: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn frames_move_from_the_previous_snapshot_to_the_current_one() {
+        let start = time::Instant::now();
+        let tick = time::Duration::from_millis(constants::THICK_RATE_MILLIS);
+        let previous = exports(10, start, [0.0, 0.0, 0.0]);
+        // Two ticks between the snapshots, the move spreads over both
+        let current = exports(12, start + 2 * tick, [4.0, -2.0, 1.0]);
+        let at = |elapsed: time::Duration| {
+            Aiomad::interpolate_exports(&previous, &current, current.timestamp + elapsed).character
+        };
+
+        assert_eq!(at(time::Duration::ZERO), [0.0, 0.0, 0.0]);
+        assert_eq!(at(tick), [2.0, -1.0, 0.5]);
+        assert_eq!(at(2 * tick), [4.0, -2.0, 1.0]);
+        assert_eq!(at(10 * tick), [4.0, -2.0, 1.0]);
+
+        // A clock read before the snapshot was taken stays on the previous one
+        let early = Aiomad::interpolate_exports(&previous, &current, start);
+        assert_eq!(early.character, [0.0, 0.0, 0.0]);
+    }
+}
@@ -1,19 +1,47 @@
 // General
 pub const THICK_RATE_MILLIS: u64 = 8;
+pub const MAX_FRAME_TIME_MILLIS: u64 = 250;
+pub const MAX_TICKS_PER_FRAME: u32 = 8;
+pub const DEFAULT_HEADLESS_TICKS: u64 = 1_000;
+pub const EXPORT_PNG_SCALE: u32 = 4;
 pub const MAX_FRAMERATE_MILLIS: u64 = 8;
-pub const N: usize = 6;
-pub const WORLD_MAP_ROWS: usize = 2_usize.pow(N as u32) + 1;
-pub const WORLD_MAP_COLS: usize = WORLD_MAP_ROWS;
+pub const DEFAULT_WORLD_MAP_WIDTH: usize = 65;
+pub const DEFAULT_WORLD_MAP_HEIGHT: usize = 65;
+pub const MIN_WORLD_MAP_SIZE: usize = 3;
 
 pub const CHUNK_MAP_ROWS: usize = 100;
 pub const CHUNK_MAP_COLS: usize = 100;
-pub const PERLIN_GRID_ROWS: usize = 4;
-pub const PERLIN_GRID_COLS: usize = PERLIN_GRID_ROWS - 1;
+pub const CHUNK_LOAD_RADIUS: i32 = 1;
+pub const CHUNK_UNLOAD_RADIUS: i32 = 2;
+pub const CHUNK_NOISE_FREQUENCY: f32 = 0.03;
+pub const CHUNK_NOISE_OCTAVES: usize = 4;
+pub const CHUNK_NOISE_WARP: f32 = 10.0;
+pub const CHUNK_ALTITUDE_SCALE: f32 = 0.1;
+pub const CHUNK_LAND_BASE: f32 = 2.0;
+pub const CHUNK_SEA_FLOOR: f32 = -2.0;
+pub const CHUNK_WATER_LEVEL: f32 = 1.0;
+pub const CHUNK_DEEP_WATER_LEVEL: f32 = -1.0;
+pub const CHUNK_BEACH_LEVEL: f32 = 1.6;
+pub const CHUNK_SNOW_LEVEL: f32 = 28.0;
+pub const CHUNK_ROCK_SLOPE: f32 = 40.0;
+pub const CHUNK_DRY_MOISTURE: f32 = 0.3;
+pub const CHARACTER_MAX_SLOPE: f32 = 45.0;
+pub const CHARACTER_WADING_SPEED: f32 = 0.5;
+pub const PATH_CLIMB_COST: f32 = 4.0;
 
 // Graphics
 pub const VSYNC: bool = true;
+pub const DEFAULT_RENDER_WIDTH: usize = 800;
+pub const DEFAULT_RENDER_HEIGHT: usize = 600;
+pub const SCREENSHOT_DIR: &str = "screenshots";
+pub const CAPTURE_BUFFER_POOL: usize = 4;
+pub const CAPTURE_SAVE_QUEUE: usize = 8;
 pub const TILE_SIZE: f32 = 0.1;
 pub const CHUNK_TILE_SIZE: f32 = 1.0;
+pub const SHADOW_MAP_SIZE: u32 = 2048;
+pub const SHADOW_BOUNDS_MARGIN: f32 = 5.0;
+pub const SHADOW_BIAS_CONSTANT: i32 = 2;
+pub const SHADOW_BIAS_SLOPE: f32 = 2.0;
 
 pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
     1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.5, 0.5, 0.0, 0.0, 0.0, 1.0,
@@ -24,3 +52,22 @@ pub const DS_ROUGHNESS: f32 = 30.0;
 pub const DS_EDGE_INIT: f32 = -70.0;
 pub const DS_CENTER_INIT: f32 = 100.0;
 pub const CA_ITER: usize = 6;
+pub const WORLD_NOISE_FREQUENCY: f32 = 0.15;
+pub const WORLD_NOISE_OCTAVES: usize = 3;
+pub const WORLD_NOISE_WARP: f32 = 2.0;
+pub const WORLD_NOISE_AMPLITUDE: f32 = 12.0;
+pub const CLIMATE_ALTITUDE_LAPSE: f32 = 0.003;
+pub const CLIMATE_SNOW_TEMPERATURE: f32 = 0.1;
+pub const CLIMATE_WATER_DISTANCE_DECAY: f32 = 6.0;
+pub const CLIMATE_BASE_RAIN: f32 = 0.04;
+pub const CLIMATE_TEMPERATURE_NOISE: f32 = 0.1;
+pub const CLIMATE_OROGRAPHIC_RAIN: f32 = 0.02;
+pub const RIVER_SOURCE_ALTITUDE: u8 = 111;
+pub const RIVER_MIN_FLOW: f32 = 12.0;
+pub const LAKE_MIN_DEPTH: f32 = 15.0;
+pub const EROSION_THERMAL_ITER: usize = 10;
+pub const WORLD_EROSION_DROPLETS: usize = 2_000;
+pub const WORLD_EROSION_TALUS: f32 = 20.0;
+pub const CHUNK_EROSION_DROPLETS: usize = 10_000;
+pub const CHUNK_EROSION_TALUS: f32 = 0.8;
+pub const CHUNK_EROSION_BORDER_FADE: usize = 8;
@@ -0,0 +1,307 @@
+use std::fmt::Write as _;
+use std::io;
+
+use image::{ImageBuffer, Luma, Rgb, RgbImage};
+
+use crate::constants;
+use crate::game::chunk_manager::ChunkCoord;
+use crate::game::chunk_map::ChunkMap;
+use crate::game::save::SaveData;
+use crate::game::world_map::{TileType, WorldMap};
+
+pub struct ExportOptions {
+    /// Colour-coded PNG of the world map.
+    pub png: Option<String>,
+    /// Print the world map to the terminal, with ANSI colours or as plain ASCII.
+    pub ansi: bool,
+    pub ascii: bool,
+    /// 16-bit grayscale PNG of the heights of `chunk`.
+    pub heightmap: Option<String>,
+    pub chunk: Option<ChunkCoord>,
+}
+
+/// Writes the requested exports of a game, e.g. a freshly generated one or a loaded save.
+/// The heightmap chunk is taken from the loaded ones if it is there, generated otherwise,
+/// and defaults to the chunk under the character.
+pub fn run(game: &SaveData, options: ExportOptions) -> io::Result<()> {
+    if let Some(path) = &options.png {
+        world_map_png(&game.world_map, constants::EXPORT_PNG_SCALE)
+            .save(path)
+            .map_err(io::Error::other)?;
+        eprintln!("World map written to {}", path);
+    }
+    if options.ansi {
+        print!("{}", world_map_ansi(&game.world_map));
+    }
+    if options.ascii {
+        print!("{}", world_map_ascii(&game.world_map));
+    }
+    if let Some(path) = &options.heightmap {
+        let [x, y, _] = game.character_position;
+        let coord = options
+            .chunk
+            .unwrap_or_else(|| ChunkCoord::from_position(x, y));
+        let chunk = match game.chunks.iter().find(|(loaded, _)| *loaded == coord) {
+            Some((_, chunk)) => chunk.clone(),
+            None => {
+                ChunkMap::for_world_tile(&game.world_map, coord.row, coord.col, game.seed).into()
+            }
+        };
+        let (heightmap, [min, max]) = chunk_heightmap(&chunk);
+        heightmap.save(path).map_err(io::Error::other)?;
+        eprintln!(
+            "Heightmap of chunk ({},{}) written to {}, black is {:.2} and white {:.2}",
+            coord.row, coord.col, path, min, max
+        );
+    }
+    Ok(())
+}
+
+/// One `scale` x `scale` square per world tile, coloured by its type and darker the lower
+/// it is.
+pub fn world_map_png(world: &WorldMap, scale: u32) -> RgbImage {
+    let tiles = &world.tiles;
+    let scale = scale.max(1);
+    let mut image = RgbImage::new(tiles.width() as u32 * scale, tiles.height() as u32 * scale);
+    for row in 0..tiles.height() {
+        for col in 0..tiles.width() {
+            let color = Rgb(shaded_color(
+                tiles[(row, col)].tile_type,
+                tiles[(row, col)].altitude,
+            ));
+            for y in 0..scale {
+                for x in 0..scale {
+                    image.put_pixel(col as u32 * scale + x, row as u32 * scale + y, color);
+                }
+            }
+        }
+    }
+    image
+}
+
+/// Two coloured spaces per world tile, for terminals with 24-bit colours.
+pub fn world_map_ansi(world: &WorldMap) -> String {
+    let tiles = &world.tiles;
+    let mut map = String::new();
+    for row in 0..tiles.height() {
+        for col in 0..tiles.width() {
+            let [r, g, b] = shaded_color(tiles[(row, col)].tile_type, tiles[(row, col)].altitude);
+            write!(map, "\x1b[48;2;{};{};{}m  ", r, g, b).unwrap();
+        }
+        map.push_str("\x1b[0m\n");
+    }
+    map
+}
+
+/// One character per world tile, see `tile_char` for the legend.
+pub fn world_map_ascii(world: &WorldMap) -> String {
+    let tiles = &world.tiles;
+    let mut map = String::with_capacity((tiles.width() + 1) * tiles.height());
+    for row in 0..tiles.height() {
+        for col in 0..tiles.width() {
+            map.push(tile_char(tiles[(row, col)].tile_type));
+        }
+        map.push('\n');
+    }
+    map
+}
+
+/// Edge heights of a chunk stretched over the whole 16-bit range, returned with the heights
+/// that black and white stand for.
+pub fn chunk_heightmap(chunk: &ChunkMap) -> (ImageBuffer<Luma<u16>, Vec<u16>>, [f32; 2]) {
+    let edges = &chunk.edges;
+    let min = edges.iter().copied().fold(f32::INFINITY, f32::min);
+    let max = edges.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let range = (max - min).max(f32::EPSILON);
+    let heightmap = ImageBuffer::from_fn(edges.width() as u32, edges.height() as u32, |x, y| {
+        let height = edges[(y as usize, x as usize)];
+        Luma([((height - min) / range * u16::MAX as f32).round() as u16])
+    });
+    (heightmap, [min, max])
+}
+
+fn tile_color(tile_type: TileType) -> [u8; 3] {
+    match tile_type {
+        TileType::WATER => [30, 70, 160],
+        TileType::LAKE => [60, 120, 200],
+        TileType::GRASS => [90, 170, 60],
+        TileType::WOODS => [30, 110, 40],
+        TileType::JUNGLE => [10, 90, 30],
+        TileType::SWAMP => [70, 90, 60],
+        TileType::STEPPE => [170, 170, 90],
+        TileType::DESERT => [220, 200, 120],
+        TileType::TUNDRA => [140, 150, 130],
+        TileType::HILLS => [130, 140, 70],
+        TileType::HIGHLANDS => [120, 110, 90],
+        TileType::MOUNTAINS => [110, 100, 100],
+        TileType::SNOW => [235, 240, 245],
+    }
+}
+
+// Tile colour from half brightness at altitude 0 to full brightness at 255
+fn shaded_color(tile_type: TileType, altitude: u8) -> [u8; 3] {
+    let shade = 0.5 + 0.5 * altitude as f32 / u8::MAX as f32;
+    tile_color(tile_type).map(|channel| (channel as f32 * shade) as u8)
+}
+
+fn tile_char(tile_type: TileType) -> char {
+    match tile_type {
+        TileType::WATER => '~',
+        TileType::LAKE => 'o',
+        TileType::GRASS => ',',
+        TileType::WOODS => 'f',
+        TileType::JUNGLE => '&',
+        TileType::SWAMP => '%',
+        TileType::STEPPE => '"',
+        TileType::DESERT => ':',
+        TileType::TUNDRA => '-',
+        TileType::HILLS => 'n',
+        TileType::HIGHLANDS => 'h',
+        TileType::MOUNTAINS => '^',
+        TileType::SNOW => '*',
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::grid::Grid;
+    use crate::game::seed::WorldSeed;
+    use crate::game::world_map::Tile;
+
+    fn tile(tile_type: TileType, altitude: u8) -> Tile {
+        Tile {
+            tile_type,
+            altitude,
+            temperature: 0.5,
+            moisture: 0.5,
+            river_flow: 0.0,
+            river_direction: None,
+        }
+    }
+
+    #[test]
+    fn png_has_a_square_per_tile() {
+        let world = WorldMap::new(WorldSeed::new(2), 9, 7);
+        let image = world_map_png(&world, 4);
+        assert_eq!(image.dimensions(), (36, 28));
+        for row in 0..7 {
+            for col in 0..9 {
+                let tile = world.tiles[(row, col)];
+                let color = Rgb(shaded_color(tile.tile_type, tile.altitude));
+                for (x, y) in [(0, 0), (3, 3)] {
+                    let pixel = image.get_pixel(col as u32 * 4 + x, row as u32 * 4 + y);
+                    assert_eq!(*pixel, color);
+                }
+            }
+        }
+        assert_eq!(world_map_png(&world, 0).dimensions(), (9, 7));
+    }
+
+    #[test]
+    fn text_maps_have_a_line_per_row() {
+        let world = WorldMap::new(WorldSeed::new(2), 9, 7);
+
+        let ascii = world_map_ascii(&world);
+        let lines: Vec<_> = ascii.lines().collect();
+        assert_eq!(lines.len(), 7);
+        for (row, line) in lines.iter().enumerate() {
+            let expected: String = (0..9)
+                .map(|col| tile_char(world.tiles[(row, col)].tile_type))
+                .collect();
+            assert_eq!(*line, expected);
+        }
+
+        let ansi = world_map_ansi(&world);
+        assert_eq!(ansi.lines().count(), 7);
+        for line in ansi.lines() {
+            assert_eq!(line.matches("\x1b[48;2;").count(), 9);
+            assert!(line.ends_with("  \x1b[0m"));
+        }
+    }
+
+    #[test]
+    fn every_tile_type_has_its_own_character_and_shade() {
+        let types = [
+            TileType::WATER,
+            TileType::LAKE,
+            TileType::GRASS,
+            TileType::WOODS,
+            TileType::JUNGLE,
+            TileType::SWAMP,
+            TileType::STEPPE,
+            TileType::DESERT,
+            TileType::TUNDRA,
+            TileType::HILLS,
+            TileType::HIGHLANDS,
+            TileType::MOUNTAINS,
+            TileType::SNOW,
+        ];
+        let world = WorldMap {
+            tiles: Grid::from_fn(types.len(), 2, |row, col| {
+                tile(types[col], row as u8 * u8::MAX)
+            }),
+        };
+        let ascii = world_map_ascii(&world);
+        assert_eq!(ascii, "~o,f&%\":-nh^*\n~o,f&%\":-nh^*\n");
+
+        // Low tiles are drawn at half the brightness of the highest ones
+        let ansi = world_map_ansi(&world);
+        let [low, high] = [0, 1].map(|row| ansi.lines().nth(row).unwrap().to_string());
+        assert!(low.starts_with("\x1b[48;2;15;35;80m  "), "{low:?}");
+        assert!(high.starts_with("\x1b[48;2;30;70;160m  "), "{high:?}");
+    }
+
+    #[test]
+    fn heightmap_spans_the_whole_range() {
+        let seed = WorldSeed::new(2);
+        let world = WorldMap::new(seed, 33, 33);
+        let chunk = ChunkMap::for_world_tile(&world, 16, 16, seed);
+        let (heightmap, [min, max]) = chunk_heightmap(&chunk);
+
+        assert_eq!(
+            heightmap.dimensions(),
+            (
+                constants::CHUNK_MAP_COLS as u32 + 1,
+                constants::CHUNK_MAP_ROWS as u32 + 1
+            )
+        );
+        assert!(min < max);
+        assert_eq!(
+            min,
+            chunk.edges.iter().copied().fold(f32::INFINITY, f32::min)
+        );
+        assert_eq!(
+            max,
+            chunk
+                .edges
+                .iter()
+                .copied()
+                .fold(f32::NEG_INFINITY, f32::max)
+        );
+        let values: Vec<u16> = heightmap.pixels().map(|pixel| pixel.0[0]).collect();
+        assert_eq!(values.iter().min(), Some(&0));
+        assert_eq!(values.iter().max(), Some(&u16::MAX));
+
+        // The highest edge is the white pixel
+        let (index, _) = chunk
+            .edges
+            .iter()
+            .enumerate()
+            .find(|(_, height)| **height == max)
+            .unwrap();
+        assert_eq!(values[index], u16::MAX);
+    }
+
+    #[test]
+    fn flat_heightmap_is_black() {
+        let chunk = ChunkMap {
+            tile_types: Grid::new(2, 2, crate::game::chunk_map::ChunkTileType::GRASS),
+            edges: Grid::new(3, 3, 4.0),
+            normals: Grid::new(3, 3, [0.0, 0.0, 1.0]),
+        };
+        let (heightmap, range) = chunk_heightmap(&chunk);
+        assert_eq!(range, [4.0, 4.0]);
+        assert!(heightmap.pixels().all(|pixel| pixel.0[0] == 0));
+    }
+}
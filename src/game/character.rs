@@ -1,5 +1,18 @@
+#[derive(Clone, Copy, Default)]
+pub struct CharacterMovement {
+    pub moving: bool,
+    pub direction: [f32; 2],
+}
+
+impl CharacterMovement {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
 pub struct Character {
     pub position: [f32; 3],
+    /// Chunk tiles per second.
     pub speed: f32,
 }
 
@@ -7,7 +20,7 @@ impl Character {
     pub fn new(position: [f32; 3]) -> Self {
         Self {
             position,
-            speed: 0.1,
+            speed: 12.5,
         }
     }
 }
@@ -0,0 +1,311 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, mpsc};
+use std::thread;
+
+use crate::constants::{CHUNK_LOAD_RADIUS, CHUNK_MAP_COLS, CHUNK_MAP_ROWS, CHUNK_UNLOAD_RADIUS};
+use crate::game::chunk_map::{ChunkMap, ChunkTileType};
+use crate::game::seed::WorldSeed;
+use crate::game::world_map::WorldMap;
+
+/// Chunk `(row, col)` covers world tile `(row, col)` and spans
+/// `[col * CHUNK_MAP_COLS, (col + 1) * CHUNK_MAP_COLS)` on x and the same with rows on y.
+/// Coordinates outside the world map are valid and extend its border tiles.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct ChunkCoord {
+    pub row: i32,
+    pub col: i32,
+}
+
+impl ChunkCoord {
+    pub fn new(row: i32, col: i32) -> Self {
+        Self { row, col }
+    }
+
+    pub fn from_position(x: f32, y: f32) -> Self {
+        Self {
+            row: (y / CHUNK_MAP_ROWS as f32).floor() as i32,
+            col: (x / CHUNK_MAP_COLS as f32).floor() as i32,
+        }
+    }
+
+    /// World-space position of the chunk's (0, 0) edge, as [x, y].
+    pub fn origin(&self) -> [f32; 2] {
+        [
+            self.col as f32 * CHUNK_MAP_COLS as f32,
+            self.row as f32 * CHUNK_MAP_ROWS as f32,
+        ]
+    }
+
+    pub fn distance(&self, other: ChunkCoord) -> i32 {
+        (self.row - other.row)
+            .abs()
+            .max((self.col - other.col).abs())
+    }
+}
+
+/// Keeps the chunks around the character loaded, generating missing ones on a worker thread
+/// and dropping the ones that got too far away.
+pub struct ChunkManager {
+    chunks: HashMap<ChunkCoord, Arc<ChunkMap>>,
+    pending: HashSet<ChunkCoord>,
+    center: ChunkCoord,
+    request_sender: Option<mpsc::Sender<ChunkCoord>>,
+    result_receiver: mpsc::Receiver<(ChunkCoord, ChunkMap)>,
+    worker: Option<thread::JoinHandle<()>>,
+    worker_running: Arc<AtomicBool>,
+}
+
+impl ChunkManager {
+    pub fn new(world: Arc<WorldMap>, seed: WorldSeed, center: ChunkCoord) -> Self {
+        Self::with_chunks(world, seed, center, Vec::new())
+    }
+
+    /// Starts from already generated chunks, e.g. the ones of a save, and only generates the
+    /// missing ones.
+    pub fn with_chunks(
+        world: Arc<WorldMap>,
+        seed: WorldSeed,
+        center: ChunkCoord,
+        chunks: Vec<(ChunkCoord, Arc<ChunkMap>)>,
+    ) -> Self {
+        let (request_sender, request_receiver) = mpsc::channel::<ChunkCoord>();
+        let (result_sender, result_receiver) = mpsc::channel();
+
+        // The chunk under the character is needed right away
+        let mut chunks: HashMap<_, _> = chunks.into_iter().collect();
+        chunks
+            .entry(center)
+            .or_insert_with(|| Arc::new(Self::generate(&world, seed, center)));
+
+        let worker_running = Arc::new(AtomicBool::new(true));
+        let worker = thread::spawn({
+            let worker_running = Arc::clone(&worker_running);
+            move || {
+                for coord in request_receiver {
+                    if !worker_running.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    let chunk = Self::generate(&world, seed, coord);
+                    if result_sender.send((coord, chunk)).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        let mut chunk_manager = Self {
+            chunks,
+            pending: HashSet::new(),
+            center,
+            request_sender: Some(request_sender),
+            result_receiver,
+            worker: Some(worker),
+            worker_running,
+        };
+        chunk_manager.request_missing();
+        chunk_manager
+    }
+
+    fn generate(world: &WorldMap, seed: WorldSeed, coord: ChunkCoord) -> ChunkMap {
+        ChunkMap::for_world_tile(world, coord.row, coord.col, seed)
+    }
+
+    pub fn update(&mut self, position: [f32; 3]) {
+        while let Ok((coord, chunk)) = self.result_receiver.try_recv() {
+            self.receive(coord, chunk);
+        }
+
+        let center = ChunkCoord::from_position(position[0], position[1]);
+        if center != self.center {
+            self.center = center;
+            self.chunks
+                .retain(|coord, _| coord.distance(center) <= CHUNK_UNLOAD_RADIUS);
+            self.request_missing();
+        }
+    }
+
+    /// Blocks until every requested chunk is generated, so that what is loaded only depends
+    /// on where the character went and not on how fast the worker is.
+    pub fn finish_pending(&mut self) {
+        while !self.pending.is_empty() {
+            let Ok((coord, chunk)) = self.result_receiver.recv() else {
+                return;
+            };
+            self.receive(coord, chunk);
+        }
+    }
+
+    fn receive(&mut self, coord: ChunkCoord, chunk: ChunkMap) {
+        self.pending.remove(&coord);
+        if coord.distance(self.center) <= CHUNK_UNLOAD_RADIUS {
+            self.chunks.insert(coord, Arc::new(chunk));
+        }
+    }
+
+    // Queues every chunk in the load radius that is neither loaded nor already queued,
+    // closest first
+    fn request_missing(&mut self) {
+        let Some(request_sender) = &self.request_sender else {
+            return;
+        };
+        let mut missing = Vec::new();
+        for row in -CHUNK_LOAD_RADIUS..=CHUNK_LOAD_RADIUS {
+            for col in -CHUNK_LOAD_RADIUS..=CHUNK_LOAD_RADIUS {
+                let coord = ChunkCoord::new(self.center.row + row, self.center.col + col);
+                if !self.chunks.contains_key(&coord) && !self.pending.contains(&coord) {
+                    missing.push(coord);
+                }
+            }
+        }
+        missing.sort_by_key(|coord| coord.distance(self.center));
+        for coord in missing {
+            if request_sender.send(coord).is_ok() {
+                self.pending.insert(coord);
+            }
+        }
+    }
+
+    pub fn loaded_chunks(&self) -> Vec<(ChunkCoord, Arc<ChunkMap>)> {
+        self.chunks
+            .iter()
+            .map(|(coord, chunk)| (*coord, Arc::clone(chunk)))
+            .collect()
+    }
+
+    /// Terrain height under a world-space position, if its chunk is loaded.
+    pub fn height_at(&self, x: f32, y: f32) -> Option<f32> {
+        let (chunk, local_x, local_y) = self.chunk_at(x, y)?;
+        Some(chunk.height_at(local_x, local_y))
+    }
+
+    /// Terrain normal under a world-space position, if its chunk is loaded.
+    pub fn normal_at(&self, x: f32, y: f32) -> Option<[f32; 3]> {
+        let (chunk, local_x, local_y) = self.chunk_at(x, y)?;
+        Some(chunk.normal_at(local_x, local_y))
+    }
+
+    /// Chunk tile type under a world-space position, if its chunk is loaded.
+    pub fn tile_type_at(&self, x: f32, y: f32) -> Option<ChunkTileType> {
+        let (chunk, local_x, local_y) = self.chunk_at(x, y)?;
+        Some(chunk.tile_type_at(local_x, local_y))
+    }
+
+    // Loaded chunk under a world-space position and the position relative to its origin
+    fn chunk_at(&self, x: f32, y: f32) -> Option<(&ChunkMap, f32, f32)> {
+        let coord = ChunkCoord::from_position(x, y);
+        let chunk = self.chunks.get(&coord)?;
+        let [origin_x, origin_y] = coord.origin();
+        Some((chunk, x - origin_x, y - origin_y))
+    }
+}
+
+impl Drop for ChunkManager {
+    fn drop(&mut self) {
+        // Skip whatever is still queued, closing the request channel then ends the worker loop
+        self.worker_running.store(false, Ordering::Relaxed);
+        self.request_sender.take();
+        // Unwrapping here would panic again while a panic unwinds, which aborts
+        if let Some(worker) = self.worker.take()
+            && worker.join().is_err()
+        {
+            eprintln!("The chunk worker panicked");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manager_at(center: ChunkCoord) -> ChunkManager {
+        let seed = WorldSeed::new(42);
+        let world = Arc::new(WorldMap::new(seed, 9, 9));
+        let mut chunk_manager = ChunkManager::new(world, seed, center);
+        chunk_manager.finish_pending();
+        chunk_manager
+    }
+
+    fn move_to(chunk_manager: &mut ChunkManager, coord: ChunkCoord) {
+        let [x, y] = coord.origin();
+        chunk_manager.update([x + 0.5, y + 0.5, 0.0]);
+        chunk_manager.finish_pending();
+    }
+
+    fn loaded(chunk_manager: &ChunkManager) -> Vec<(i32, i32)> {
+        let mut coords: Vec<_> = chunk_manager
+            .loaded_chunks()
+            .into_iter()
+            .map(|(coord, _)| (coord.row, coord.col))
+            .collect();
+        coords.sort();
+        coords
+    }
+
+    fn block(
+        rows: std::ops::RangeInclusive<i32>,
+        cols: std::ops::RangeInclusive<i32>,
+    ) -> Vec<(i32, i32)> {
+        let mut coords = Vec::new();
+        for row in rows {
+            for col in cols.clone() {
+                coords.push((row, col));
+            }
+        }
+        coords
+    }
+
+    #[test]
+    fn chunks_in_the_load_radius_are_generated() {
+        let chunk_manager = manager_at(ChunkCoord::new(4, 4));
+        assert_eq!(loaded(&chunk_manager), block(3..=5, 3..=5));
+        assert!(chunk_manager.height_at(450.0, 450.0).is_some());
+        assert!(chunk_manager.height_at(650.0, 450.0).is_none());
+    }
+
+    #[test]
+    fn moving_loads_ahead_and_drops_past_the_unload_radius() {
+        let mut chunk_manager = manager_at(ChunkCoord::new(4, 4));
+
+        // One chunk east, the west column is still inside the unload radius
+        move_to(&mut chunk_manager, ChunkCoord::new(4, 5));
+        assert_eq!(loaded(&chunk_manager), block(3..=5, 3..=6));
+
+        // Two more, everything further than the unload radius from column 7 goes
+        move_to(&mut chunk_manager, ChunkCoord::new(4, 7));
+        assert_eq!(loaded(&chunk_manager), block(3..=5, 5..=8));
+
+        // Diagonally back, the ring around the new center is added to what is still close
+        move_to(&mut chunk_manager, ChunkCoord::new(6, 6));
+        let mut expected = [block(4..=5, 5..=8), block(6..=7, 5..=7)].concat();
+        expected.sort();
+        assert_eq!(loaded(&chunk_manager), expected);
+    }
+
+    #[test]
+    fn chunks_arriving_after_the_character_left_are_dropped() {
+        let seed = WorldSeed::new(42);
+        let world = WorldMap::new(seed, 9, 9);
+        let mut chunk_manager = manager_at(ChunkCoord::new(4, 4));
+
+        let far = ChunkCoord::new(4, 4 + CHUNK_UNLOAD_RADIUS + 1);
+        chunk_manager.pending.insert(far);
+        chunk_manager.receive(far, ChunkManager::generate(&world, seed, far));
+        assert!(chunk_manager.pending.is_empty());
+        assert_eq!(loaded(&chunk_manager), block(3..=5, 3..=5));
+    }
+
+    #[test]
+    fn worker_chunks_match_direct_generation() {
+        let seed = WorldSeed::new(42);
+        let world = WorldMap::new(seed, 9, 9);
+        let chunk_manager = manager_at(ChunkCoord::new(4, 4));
+        for (coord, chunk) in chunk_manager.loaded_chunks() {
+            assert_eq!(
+                *chunk,
+                ChunkManager::generate(&world, seed, coord),
+                "{coord:?}"
+            );
+        }
+    }
+}
@@ -1,118 +1,557 @@
-use crate::constants::{CHUNK_MAP_COLS, CHUNK_MAP_ROWS, PERLIN_GRID_COLS, PERLIN_GRID_ROWS};
+use crate::constants::{
+    CHUNK_ALTITUDE_SCALE, CHUNK_BEACH_LEVEL, CHUNK_DEEP_WATER_LEVEL, CHUNK_DRY_MOISTURE,
+    CHUNK_EROSION_BORDER_FADE, CHUNK_EROSION_DROPLETS, CHUNK_EROSION_TALUS, CHUNK_LAND_BASE,
+    CHUNK_MAP_COLS, CHUNK_MAP_ROWS, CHUNK_NOISE_FREQUENCY, CHUNK_NOISE_OCTAVES, CHUNK_NOISE_WARP,
+    CHUNK_ROCK_SLOPE, CHUNK_SEA_FLOOR, CHUNK_SNOW_LEVEL, CHUNK_TILE_SIZE, CHUNK_WATER_LEVEL,
+    EROSION_THERMAL_ITER,
+};
+use crate::game::erosion::{HydraulicErosion, ThermalErosion};
+use crate::game::grid::Grid;
+use crate::game::noise::{DomainWarp, Fractal, Noise, Perlin, Simplex};
+use crate::game::seed::WorldSeed;
+use crate::game::world_map::{Tile, TileType, WorldMap};
 
-#[derive(Copy, Clone, PartialEq)]
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone, PartialEq, Debug)]
 pub enum ChunkTileType {
-    WATER,
+    DEEP_WATER,
+    SHALLOW_WATER,
+    SAND,
+    DIRT,
     GRASS,
+    FOREST_FLOOR,
+    ROCK,
+    SNOW,
 }
 
+// Detail added on top of the world tiles, sampled in chunk tile coordinates
+struct TerrainNoise {
+    rolling: DomainWarp<Fractal<Perlin>, Fractal<Simplex>>,
+    ridges: Fractal<Perlin>,
+}
+
+#[derive(PartialEq, Debug)]
 pub struct ChunkMap {
-    pub tile_types: Vec<Vec<ChunkTileType>>,
-    pub altitudes: Vec<Vec<f32>>,
-    pub edges: Vec<Vec<f32>>,
+    pub tile_types: Grid<ChunkTileType>,
+    pub edges: Grid<f32>,
+    pub normals: Grid<[f32; 3]>,
 }
 
 impl ChunkMap {
-    pub fn new() -> Self {
-        let edges = Self::perlin_noise();
+    /// Local terrain of the world tile at `(row, col)`. The base height and roughness are
+    /// interpolated between the centers of the neighbouring world tiles and the detail comes
+    /// from a noise sampled in world space, so two adjacent chunks share the exact same edge
+    /// heights along their border. Tiles outside the world map continue its border tiles.
+    pub fn for_world_tile(world: &WorldMap, row: i32, col: i32, seed: WorldSeed) -> Self {
+        let first_edge_row = row as i64 * CHUNK_MAP_ROWS as i64 - 1;
+        let first_edge_col = col as i64 * CHUNK_MAP_COLS as i64 - 1;
+        let noise = Self::terrain_noise(seed);
+        let mut padded_edges = Grid::from_fn(CHUNK_MAP_COLS + 3, CHUNK_MAP_ROWS + 3, |r, c| {
+            Self::raw_height(
+                &world.tiles,
+                &noise,
+                first_edge_row + r as i64,
+                first_edge_col + c as i64,
+            )
+        });
+
+        let mut edges = Grid::from_fn(CHUNK_MAP_COLS + 1, CHUNK_MAP_ROWS + 1, |r, c| {
+            padded_edges[(r + 1, c + 1)]
+        });
+        Self::erode(&mut edges, seed.chunk_seed(row, col).erosion_seed());
+        for edge_row in 0..=CHUNK_MAP_ROWS {
+            for edge_col in 0..=CHUNK_MAP_COLS {
+                padded_edges[(edge_row + 1, edge_col + 1)] = edges[(edge_row, edge_col)];
+            }
+        }
+
         let altitudes = Self::give_altitudes(&edges);
-        let tile_types = Self::give_types(&altitudes);
+        let normals = Self::give_normals(&padded_edges);
+        let world_tile = world.tiles[(
+            row.clamp(0, world.tiles.height() as i32 - 1) as usize,
+            col.clamp(0, world.tiles.width() as i32 - 1) as usize,
+        )];
+        let tile_types = Self::give_types(&altitudes, &normals, &world_tile);
 
         Self {
             tile_types,
-            altitudes,
             edges,
+            normals,
         }
     }
 
-    pub fn perlin_noise() -> Vec<Vec<f32>> {
-        let mut ground = vec![vec![0.0; CHUNK_MAP_COLS + 1]; CHUNK_MAP_ROWS + 1];
-        let mut vec_grid = vec![vec![(0.0, 0.0); PERLIN_GRID_COLS + 1]; PERLIN_GRID_ROWS + 1];
+    /// Height of the rendered surface at `(x, y)`, given in chunk tiles from the chunk's (0, 0)
+    /// edge. Interpolates over the same two triangles per tile as `ChunkTile::new`, so the
+    /// result lies exactly on the drawn terrain.
+    pub fn height_at(&self, x: f32, y: f32) -> f32 {
+        let (row, col, weights) = Self::triangle_weights(x, y);
+        let corners = [
+            self.edges[(row, col)],
+            self.edges[(row + 1, col)],
+            self.edges[(row, col + 1)],
+            self.edges[(row + 1, col + 1)],
+        ];
+        (0..4).map(|corner| corners[corner] * weights[corner]).sum()
+    }
 
-        let perlin_grid_cols = PERLIN_GRID_COLS as f32;
-        let perlin_grid_rows: f32 = PERLIN_GRID_ROWS as f32;
+    /// Type of the tile under `(x, y)`, given in chunk tiles from the chunk's (0, 0) edge.
+    pub fn tile_type_at(&self, x: f32, y: f32) -> ChunkTileType {
+        let (row, col, _) = Self::triangle_weights(x, y);
+        self.tile_types[(row, col)]
+    }
 
-        for row in 0..=PERLIN_GRID_ROWS {
-            for col in 0..=PERLIN_GRID_COLS {
-                let theta: f32 = rand::random_range(0.0..2.0 * std::f32::consts::PI);
-                vec_grid[row][col] = (theta.cos(), theta.sin());
+    /// Unit surface normal at `(x, y)`, the edge normals blended like the shading of the
+    /// rendered triangle under that point.
+    pub fn normal_at(&self, x: f32, y: f32) -> [f32; 3] {
+        let (row, col, weights) = Self::triangle_weights(x, y);
+        let corners = [
+            self.normals[(row, col)],
+            self.normals[(row + 1, col)],
+            self.normals[(row, col + 1)],
+            self.normals[(row + 1, col + 1)],
+        ];
+        let mut normal = [0.0; 3];
+        for corner in 0..4 {
+            for axis in 0..3 {
+                normal[axis] += corners[corner][axis] * weights[corner];
             }
         }
+        let length =
+            f32::sqrt(normal[0] * normal[0] + normal[1] * normal[1] + normal[2] * normal[2]);
+        [normal[0] / length, normal[1] / length, normal[2] / length]
+    }
+
+    // Tile under (x, y) and the barycentric weights of its corners (row, col), (row + 1, col),
+    // (row, col + 1) and (row + 1, col + 1). The tile is split along the diagonal from
+    // (row, col) to (row + 1, col + 1), with the triangle (0, 2, 3) on the side where x >= y.
+    fn triangle_weights(x: f32, y: f32) -> (usize, usize, [f32; 4]) {
+        let x = x.clamp(0.0, CHUNK_MAP_COLS as f32);
+        let y = y.clamp(0.0, CHUNK_MAP_ROWS as f32);
+        let col = (x as usize).min(CHUNK_MAP_COLS - 1);
+        let row = (y as usize).min(CHUNK_MAP_ROWS - 1);
+        let (fx, fy) = (x - col as f32, y - row as f32);
+
+        let weights = if fx >= fy {
+            [1.0 - fx, 0.0, fx - fy, fy]
+        } else {
+            [1.0 - fy, fy - fx, 0.0, fx]
+        };
+        (row, col, weights)
+    }
+
+    // Height before erosion of the edge at global edge coordinates, the same for every chunk
+    // that contains it
+    fn raw_height(tiles: &Grid<Tile>, noise: &TerrainNoise, edge_row: i64, edge_col: i64) -> f32 {
+        let world_row = edge_row as f32 / CHUNK_MAP_ROWS as f32;
+        let world_col = edge_col as f32 / CHUNK_MAP_COLS as f32;
+        let [base, roughness, ridges] = Self::sample_world(tiles, world_row, world_col);
+        let (x, y) = (edge_col as f32, edge_row as f32);
+        let detail = noise.rolling.get(x, y) * (1.0 - ridges) + noise.ridges.get(x, y) * ridges;
+        base + roughness * detail
+    }
+
+    // Erosion only reshapes the inside of the chunk: its effect fades out towards the
+    // borders and leaves the two outer rings of edges untouched, as the neighbouring chunks
+    // can't know about it
+    fn erode(edges: &mut Grid<f32>, seed: WorldSeed) {
+        let raw = edges.clone();
+        ThermalErosion::new(EROSION_THERMAL_ITER, CHUNK_EROSION_TALUS).erode(edges);
+        HydraulicErosion::new(CHUNK_EROSION_DROPLETS).erode(edges, seed);
+
         for row in 0..=CHUNK_MAP_ROWS {
             for col in 0..=CHUNK_MAP_COLS {
-                let grid_cell_col = (col * PERLIN_GRID_COLS) / (CHUNK_MAP_COLS + 1);
-                let grid_cell_row = (row * PERLIN_GRID_ROWS) / (CHUNK_MAP_ROWS + 1);
-                let grid_cell_offset_x = (col as f32 / (CHUNK_MAP_COLS + 1) as f32
-                    * perlin_grid_cols)
-                    - grid_cell_col as f32;
-                let grid_cell_offset_y = (row as f32 / (CHUNK_MAP_ROWS + 1) as f32
-                    * perlin_grid_rows)
-                    - grid_cell_row as f32;
-
-                let corner_tl = vec_grid[grid_cell_row][grid_cell_col];
-                let corner_tr = vec_grid[grid_cell_row][grid_cell_col + 1];
-                let corner_bl = vec_grid[grid_cell_row + 1][grid_cell_col];
-                let corner_br = vec_grid[grid_cell_row + 1][grid_cell_col + 1];
-
-                let offset_vector_tl = (grid_cell_offset_x, grid_cell_offset_y);
-                let offset_vector_tr = (grid_cell_offset_x - 1.0, grid_cell_offset_y);
-                let offset_vector_bl = (grid_cell_offset_x, grid_cell_offset_y - 1.0);
-                let offset_vector_br = (grid_cell_offset_x - 1.0, grid_cell_offset_y - 1.0);
-
-                let dot_product_tl =
-                    corner_tl.0 * offset_vector_tl.0 + corner_tl.1 * offset_vector_tl.1;
-                let dot_product_tr =
-                    corner_tr.0 * offset_vector_tr.0 + corner_tr.1 * offset_vector_tr.1;
-                let dot_product_bl =
-                    corner_bl.0 * offset_vector_bl.0 + corner_bl.1 * offset_vector_bl.1;
-                let dot_product_br =
-                    corner_br.0 * offset_vector_br.0 + corner_br.1 * offset_vector_br.1;
-
-                let interpolate = |a0: f32, a1: f32, w: f32| (1.0 - w) * a0 + w * a1;
-                let fade = |t: f32| t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
-
-                let height = interpolate(
-                    interpolate(dot_product_tl, dot_product_bl, fade(grid_cell_offset_y)),
-                    interpolate(dot_product_tr, dot_product_br, fade(grid_cell_offset_y)),
-                    fade(grid_cell_offset_x),
-                );
-                if height < 0.0 {
-                    ground[row][col] = 0.0;
-                } else {
-                    ground[row][col] = height * 10.0;
-                }
+                let border_distance = row
+                    .min(col)
+                    .min(CHUNK_MAP_ROWS - row)
+                    .min(CHUNK_MAP_COLS - col);
+                let weight = (border_distance.saturating_sub(1) as f32
+                    / CHUNK_EROSION_BORDER_FADE as f32)
+                    .min(1.0);
+                edges[(row, col)] =
+                    raw[(row, col)] + (edges[(row, col)] - raw[(row, col)]) * weight;
             }
         }
-        ground
     }
 
-    fn give_altitudes(edges: &Vec<Vec<f32>>) -> Vec<Vec<f32>> {
-        let mut altitudes = vec![vec![0.0; CHUNK_MAP_COLS]; CHUNK_MAP_ROWS];
+    fn terrain_noise(seed: WorldSeed) -> TerrainNoise {
+        let seed = seed.noise_seed();
+        TerrainNoise {
+            rolling: DomainWarp::new(
+                Fractal::fbm(Perlin::new(seed), CHUNK_NOISE_OCTAVES)
+                    .frequency(CHUNK_NOISE_FREQUENCY),
+                Fractal::fbm(Simplex::new(seed.noise_seed()), 2)
+                    .frequency(CHUNK_NOISE_FREQUENCY / 2.0),
+                CHUNK_NOISE_WARP,
+            ),
+            ridges: Fractal::ridged(
+                Perlin::new(seed.noise_seed().noise_seed()),
+                CHUNK_NOISE_OCTAVES,
+            )
+            .frequency(CHUNK_NOISE_FREQUENCY)
+            .gain(0.6),
+        }
+    }
+
+    // Bilinear interpolation of the terrain profiles between world tile centers,
+    // positions are in world tile units
+    fn sample_world(tiles: &Grid<Tile>, world_row: f32, world_col: f32) -> [f32; 3] {
+        let row = (world_row - 0.5).clamp(0.0, (tiles.height() - 1) as f32);
+        let col = (world_col - 0.5).clamp(0.0, (tiles.width() - 1) as f32);
+        let (row0, col0) = (row as usize, col as usize);
+        let (row1, col1) = (
+            (row0 + 1).min(tiles.height() - 1),
+            (col0 + 1).min(tiles.width() - 1),
+        );
+        let (y, x) = (row - row0 as f32, col - col0 as f32);
+
+        let lerp = |a: [f32; 3], b: [f32; 3], t: f32| {
+            [
+                a[0] + (b[0] - a[0]) * t,
+                a[1] + (b[1] - a[1]) * t,
+                a[2] + (b[2] - a[2]) * t,
+            ]
+        };
+        let top = lerp(
+            Self::terrain_profile(&tiles[(row0, col0)]),
+            Self::terrain_profile(&tiles[(row0, col1)]),
+            x,
+        );
+        let bottom = lerp(
+            Self::terrain_profile(&tiles[(row1, col0)]),
+            Self::terrain_profile(&tiles[(row1, col1)]),
+            x,
+        );
+        lerp(top, bottom, y)
+    }
+
+    // [base height, noise amplitude, share of ridged noise] of the local terrain of a world tile
+    fn terrain_profile(tile: &Tile) -> [f32; 3] {
+        let roughness = match tile.tile_type {
+            TileType::WATER | TileType::LAKE => 0.5,
+            TileType::SWAMP => 0.8,
+            TileType::GRASS | TileType::STEPPE | TileType::DESERT | TileType::TUNDRA => 2.0,
+            TileType::WOODS | TileType::JUNGLE => 3.0,
+            TileType::HILLS => 8.0,
+            TileType::SNOW => 10.0,
+            TileType::HIGHLANDS => 12.0,
+            TileType::MOUNTAINS => 20.0,
+        };
+        let base = match tile.tile_type {
+            TileType::WATER | TileType::LAKE => CHUNK_SEA_FLOOR,
+            _ => CHUNK_LAND_BASE + tile.altitude as f32 * CHUNK_ALTITUDE_SCALE,
+        };
+        let ridges = match tile.tile_type {
+            TileType::MOUNTAINS => 1.0,
+            TileType::HIGHLANDS | TileType::SNOW => 0.5,
+            TileType::HILLS => 0.2,
+            _ => 0.0,
+        };
+        [base, roughness, ridges]
+    }
+
+    fn give_altitudes(edges: &Grid<f32>) -> Grid<f32> {
+        let mut altitudes = Grid::new(CHUNK_MAP_COLS, CHUNK_MAP_ROWS, 0.0);
 
         for row in 0..CHUNK_MAP_ROWS {
             for col in 0..CHUNK_MAP_COLS {
-                altitudes[row][col] = (edges[row][col]
-                    + edges[row + 1][col]
-                    + edges[row][col + 1]
-                    + edges[row + 1][col + 1])
+                altitudes[(row, col)] = (edges[(row, col)]
+                    + edges[(row + 1, col)]
+                    + edges[(row, col + 1)]
+                    + edges[(row + 1, col + 1)])
                     / 4.0;
             }
         }
         altitudes
     }
 
-    fn give_types(altitudes: &Vec<Vec<f32>>) -> Vec<Vec<ChunkTileType>> {
-        let mut types = vec![vec![ChunkTileType::WATER; CHUNK_MAP_COLS]; CHUNK_MAP_ROWS];
+    // Central differences over the padded edges, so the border edges look into the
+    // neighbouring chunks and both sides of a seam end up with the same normal
+    fn give_normals(padded_edges: &Grid<f32>) -> Grid<[f32; 3]> {
+        Grid::from_fn(CHUNK_MAP_COLS + 1, CHUNK_MAP_ROWS + 1, |row, col| {
+            let (row, col) = (row + 1, col + 1);
+            let dzdx = (padded_edges[(row, col + 1)] - padded_edges[(row, col - 1)])
+                / (2.0 * CHUNK_TILE_SIZE);
+            let dzdy = (padded_edges[(row + 1, col)] - padded_edges[(row - 1, col)])
+                / (2.0 * CHUNK_TILE_SIZE);
+            let length = f32::sqrt(dzdx * dzdx + dzdy * dzdy + 1.0);
+            [-dzdx / length, -dzdy / length, 1.0 / length]
+        })
+    }
+
+    fn give_types(
+        altitudes: &Grid<f32>,
+        normals: &Grid<[f32; 3]>,
+        world_tile: &Tile,
+    ) -> Grid<ChunkTileType> {
+        let mut types = Grid::new(CHUNK_MAP_COLS, CHUNK_MAP_ROWS, ChunkTileType::DEEP_WATER);
 
         for row in 0..CHUNK_MAP_ROWS {
             for col in 0..CHUNK_MAP_COLS {
-                let tile_type = match altitudes[row][col] as u32 {
-                    0 => ChunkTileType::WATER,
-                    _ => ChunkTileType::GRASS,
-                };
-                types[row][col] = tile_type;
+                let altitude = altitudes[(row, col)];
+                let up = (normals[(row, col)][2]
+                    + normals[(row + 1, col)][2]
+                    + normals[(row, col + 1)][2]
+                    + normals[(row + 1, col + 1)][2])
+                    / 4.0;
+                let slope = up.clamp(-1.0, 1.0).acos().to_degrees();
+
+                types[(row, col)] = Self::tile_type(altitude, slope, world_tile);
             }
         }
         types
     }
+
+    // Water and beaches go by altitude, cliffs and peaks by slope and altitude, everything
+    // else follows the biome of the world tile
+    fn tile_type(altitude: f32, slope: f32, world_tile: &Tile) -> ChunkTileType {
+        if altitude < CHUNK_DEEP_WATER_LEVEL {
+            return ChunkTileType::DEEP_WATER;
+        }
+        if altitude < CHUNK_WATER_LEVEL {
+            return ChunkTileType::SHALLOW_WATER;
+        }
+        if slope > CHUNK_ROCK_SLOPE {
+            return ChunkTileType::ROCK;
+        }
+        if altitude > CHUNK_SNOW_LEVEL {
+            return ChunkTileType::SNOW;
+        }
+        let beach = !matches!(world_tile.tile_type, TileType::SWAMP | TileType::SNOW);
+        if altitude < CHUNK_BEACH_LEVEL && beach {
+            return ChunkTileType::SAND;
+        }
+
+        match world_tile.tile_type {
+            TileType::WATER | TileType::DESERT => ChunkTileType::SAND,
+            TileType::SNOW => ChunkTileType::SNOW,
+            TileType::WOODS | TileType::JUNGLE => ChunkTileType::FOREST_FLOOR,
+            TileType::SWAMP | TileType::LAKE => ChunkTileType::DIRT,
+            TileType::MOUNTAINS | TileType::HIGHLANDS => {
+                if slope > CHUNK_ROCK_SLOPE / 2.0 {
+                    ChunkTileType::ROCK
+                } else {
+                    ChunkTileType::DIRT
+                }
+            }
+            TileType::STEPPE | TileType::TUNDRA | TileType::HILLS | TileType::GRASS => {
+                if world_tile.moisture < CHUNK_DRY_MOISTURE {
+                    ChunkTileType::DIRT
+                } else {
+                    ChunkTileType::GRASS
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn neighbouring_chunks_share_their_border() {
+        let seed = WorldSeed::new(42);
+        let world = WorldMap::new(seed, 9, 9);
+        let chunk = ChunkMap::for_world_tile(&world, 4, 4, seed);
+        let east = ChunkMap::for_world_tile(&world, 4, 5, seed);
+        let south = ChunkMap::for_world_tile(&world, 5, 4, seed);
+
+        for row in 0..=CHUNK_MAP_ROWS {
+            assert_eq!(
+                chunk.edges[(row, CHUNK_MAP_COLS)],
+                east.edges[(row, 0)],
+                "east border height, row {row}"
+            );
+            assert_eq!(
+                chunk.normals[(row, CHUNK_MAP_COLS)],
+                east.normals[(row, 0)],
+                "east border normal, row {row}"
+            );
+        }
+        for col in 0..=CHUNK_MAP_COLS {
+            assert_eq!(
+                chunk.edges[(CHUNK_MAP_ROWS, col)],
+                south.edges[(0, col)],
+                "south border height, col {col}"
+            );
+            assert_eq!(
+                chunk.normals[(CHUNK_MAP_ROWS, col)],
+                south.normals[(0, col)],
+                "south border normal, col {col}"
+            );
+        }
+    }
+
+    // Barycentric coordinates of p in the triangle (a, b, c), all in the xy plane
+    fn barycentric(p: [f32; 2], a: [f32; 2], b: [f32; 2], c: [f32; 2]) -> [f32; 3] {
+        let area = |p: [f32; 2], q: [f32; 2], r: [f32; 2]| {
+            (q[0] - p[0]) * (r[1] - p[1]) - (r[0] - p[0]) * (q[1] - p[1])
+        };
+        let total = area(a, b, c);
+        [
+            area(p, b, c) / total,
+            area(a, p, c) / total,
+            area(a, b, p) / total,
+        ]
+    }
+
+    #[test]
+    fn height_and_normal_lie_on_the_rendered_triangles() {
+        let seed = WorldSeed::new(42);
+        let world = WorldMap::new(seed, 9, 9);
+        let chunk = ChunkMap::for_world_tile(&world, 4, 4, seed);
+        let (row, col) = (37, 52);
+        let corner = |dr: usize, dc: usize| {
+            (
+                [(col + dc) as f32, (row + dr) as f32],
+                chunk.edges[(row + dr, col + dc)],
+                chunk.normals[(row + dr, col + dc)],
+            )
+        };
+        // A planar tile would hide which diagonal the triangles are split along
+        let twist = corner(0, 0).1 + corner(1, 1).1 - corner(0, 1).1 - corner(1, 0).1;
+        assert!(twist.abs() > 1e-3, "tile ({row}, {col}) is planar");
+
+        // The two triangles drawn by ChunkTile::new, split along the (0, 0)-(1, 1) diagonal
+        let triangles = [
+            ([corner(0, 0), corner(0, 1), corner(1, 1)], (0.7, 0.2)),
+            ([corner(1, 1), corner(1, 0), corner(0, 0)], (0.2, 0.7)),
+        ];
+
+        for (vertices, (fx, fy)) in triangles {
+            for (position, height, normal) in vertices {
+                assert!((chunk.height_at(position[0], position[1]) - height).abs() < 1e-4);
+                let at_vertex = chunk.normal_at(position[0], position[1]);
+                for axis in 0..3 {
+                    assert!((at_vertex[axis] - normal[axis]).abs() < 1e-4);
+                }
+            }
+
+            let point = [col as f32 + fx, row as f32 + fy];
+            let weights = barycentric(point, vertices[0].0, vertices[1].0, vertices[2].0);
+            let expected: f32 = (0..3).map(|i| vertices[i].1 * weights[i]).sum();
+            assert!((chunk.height_at(point[0], point[1]) - expected).abs() < 1e-4);
+
+            let blended: [f32; 3] =
+                std::array::from_fn(|axis| (0..3).map(|i| vertices[i].2[axis] * weights[i]).sum());
+            let length = blended.iter().map(|v| v * v).sum::<f32>().sqrt();
+            let normal = chunk.normal_at(point[0], point[1]);
+            for axis in 0..3 {
+                assert!((normal[axis] - blended[axis] / length).abs() < 1e-4);
+            }
+        }
+    }
+
+    fn world_tile(tile_type: TileType, altitude: u8, moisture: f32) -> Tile {
+        Tile {
+            tile_type,
+            altitude,
+            temperature: 0.5,
+            moisture,
+            river_flow: 0.0,
+            river_direction: None,
+        }
+    }
+
+    // Chunk of the center tile of a world made of a single tile type
+    fn uniform_chunk(tile_type: TileType, altitude: u8) -> ChunkMap {
+        let world = WorldMap {
+            tiles: Grid::new(5, 5, world_tile(tile_type, altitude, 0.6)),
+        };
+        ChunkMap::for_world_tile(&world, 2, 2, WorldSeed::new(3))
+    }
+
+    fn share_of(chunk: &ChunkMap, types: &[ChunkTileType]) -> f32 {
+        let mut count = 0;
+        for row in 0..CHUNK_MAP_ROWS {
+            for col in 0..CHUNK_MAP_COLS {
+                if types.contains(&chunk.tile_types[(row, col)]) {
+                    count += 1;
+                }
+            }
+        }
+        count as f32 / (CHUNK_MAP_ROWS * CHUNK_MAP_COLS) as f32
+    }
+
+    fn mean_height(chunk: &ChunkMap) -> f32 {
+        let mut total = 0.0;
+        for row in 0..=CHUNK_MAP_ROWS {
+            for col in 0..=CHUNK_MAP_COLS {
+                total += chunk.edges[(row, col)];
+            }
+        }
+        total / ((CHUNK_MAP_ROWS + 1) * (CHUNK_MAP_COLS + 1)) as f32
+    }
+
+    #[test]
+    fn chunk_terrain_follows_its_world_tile() {
+        let water = [ChunkTileType::DEEP_WATER, ChunkTileType::SHALLOW_WATER];
+        for tile_type in [TileType::WATER, TileType::LAKE] {
+            let chunk = uniform_chunk(tile_type, 0);
+            assert_eq!(share_of(&chunk, &water), 1.0, "{tile_type:?}");
+        }
+
+        let woods = uniform_chunk(TileType::WOODS, 100);
+        assert!(share_of(&woods, &[ChunkTileType::FOREST_FLOOR]) > 0.5);
+        let desert = uniform_chunk(TileType::DESERT, 100);
+        assert!(share_of(&desert, &[ChunkTileType::SAND]) > 0.5);
+        let grass = uniform_chunk(TileType::GRASS, 100);
+        assert!(share_of(&grass, &[ChunkTileType::GRASS]) > 0.5);
+
+        // Higher and rougher world tiles give higher and rockier chunks
+        let mountains = uniform_chunk(TileType::MOUNTAINS, 200);
+        assert!(mean_height(&mountains) > mean_height(&woods));
+        assert!(mean_height(&woods) > mean_height(&uniform_chunk(TileType::WATER, 0)));
+        assert!(
+            share_of(&mountains, &[ChunkTileType::ROCK]) > share_of(&woods, &[ChunkTileType::ROCK])
+        );
+    }
+
+    #[test]
+    fn tile_types_follow_the_altitude_and_slope_thresholds() {
+        use ChunkTileType as Chunk;
+        use TileType as World;
+        let (deep, water, beach) = (CHUNK_DEEP_WATER_LEVEL, CHUNK_WATER_LEVEL, CHUNK_BEACH_LEVEL);
+        let (snow, cliff) = (CHUNK_SNOW_LEVEL, CHUNK_ROCK_SLOPE);
+        let (wet, dry) = (0.6, CHUNK_DRY_MOISTURE - 0.1);
+        let below = |level: f32| level - 0.01;
+        let above = |level: f32| level + 0.01;
+        let cases = [
+            // Water by altitude whatever the world tile and the slope
+            (below(deep), 80.0, World::MOUNTAINS, wet, Chunk::DEEP_WATER),
+            (above(deep), 0.0, World::GRASS, wet, Chunk::SHALLOW_WATER),
+            (below(water), 0.0, World::DESERT, wet, Chunk::SHALLOW_WATER),
+            // Cliffs, then peaks
+            (above(water), above(cliff), World::GRASS, wet, Chunk::ROCK),
+            (above(snow), above(cliff), World::GRASS, wet, Chunk::ROCK),
+            (above(snow), below(cliff), World::GRASS, wet, Chunk::SNOW),
+            // Beaches, except in swamps and snow
+            (above(water), 0.0, World::WOODS, wet, Chunk::SAND),
+            (below(beach), 0.0, World::SWAMP, wet, Chunk::DIRT),
+            (below(beach), 0.0, World::SNOW, wet, Chunk::SNOW),
+            // The world tile biome above the beach
+            (above(beach), 0.0, World::WATER, wet, Chunk::SAND),
+            (above(beach), 0.0, World::DESERT, wet, Chunk::SAND),
+            (above(beach), 0.0, World::SNOW, wet, Chunk::SNOW),
+            (above(beach), 0.0, World::WOODS, wet, Chunk::FOREST_FLOOR),
+            (above(beach), 0.0, World::JUNGLE, wet, Chunk::FOREST_FLOOR),
+            (above(beach), 0.0, World::SWAMP, wet, Chunk::DIRT),
+            (above(beach), 0.0, World::LAKE, wet, Chunk::DIRT),
+            (10.0, below(cliff / 2.0), World::MOUNTAINS, wet, Chunk::DIRT),
+            (10.0, above(cliff / 2.0), World::HIGHLANDS, wet, Chunk::ROCK),
+            (10.0, 0.0, World::GRASS, wet, Chunk::GRASS),
+            (10.0, 0.0, World::HILLS, wet, Chunk::GRASS),
+            (10.0, 0.0, World::STEPPE, dry, Chunk::DIRT),
+            (10.0, 0.0, World::TUNDRA, dry, Chunk::DIRT),
+        ];
+
+        for (altitude, slope, tile_type, moisture, expected) in cases {
+            let tile = world_tile(tile_type, 100, moisture);
+            assert_eq!(
+                ChunkMap::tile_type(altitude, slope, &tile),
+                expected,
+                "altitude {altitude}, slope {slope}, {tile_type:?}, moisture {moisture}"
+            );
+        }
+    }
 }
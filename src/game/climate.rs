@@ -0,0 +1,224 @@
+use std::collections::VecDeque;
+
+use crate::constants::{
+    CLIMATE_ALTITUDE_LAPSE, CLIMATE_BASE_RAIN, CLIMATE_OROGRAPHIC_RAIN, CLIMATE_SNOW_TEMPERATURE,
+    CLIMATE_TEMPERATURE_NOISE, CLIMATE_WATER_DISTANCE_DECAY,
+};
+use crate::game::grid::Grid;
+use crate::game::noise::{Fractal, Noise, Simplex};
+use crate::game::seed::WorldSeed;
+use crate::game::world_map::{Tile, TileType};
+
+// Upper bounds of the first three bands, the last band takes everything above
+const TEMPERATURE_BANDS: [f32; 3] = [0.25, 0.5, 0.75];
+const MOISTURE_BANDS: [f32; 3] = [0.25, 0.5, 0.9];
+
+// Whittaker-style lookup, rows are temperature bands (cold -> hot),
+// columns are moisture bands (dry -> wet)
+const BIOME_TABLE: [[TileType; 4]; 4] = [
+    [
+        TileType::TUNDRA,
+        TileType::TUNDRA,
+        TileType::WOODS,
+        TileType::SWAMP,
+    ],
+    [
+        TileType::STEPPE,
+        TileType::GRASS,
+        TileType::WOODS,
+        TileType::SWAMP,
+    ],
+    [
+        TileType::STEPPE,
+        TileType::GRASS,
+        TileType::WOODS,
+        TileType::SWAMP,
+    ],
+    [
+        TileType::DESERT,
+        TileType::STEPPE,
+        TileType::GRASS,
+        TileType::JUNGLE,
+    ],
+];
+
+/// Temperature and moisture fields of the world map, both normalized to `0.0..=1.0`.
+pub struct Climate {
+    pub temperature: Grid<f32>,
+    pub moisture: Grid<f32>,
+}
+
+impl Climate {
+    pub fn new(tiles: &Grid<Tile>, seed: WorldSeed) -> Self {
+        let temperature = Self::give_temperature(tiles, seed);
+        let water_proximity = Self::give_water_proximity(tiles);
+        let wind_moisture = Self::give_wind_moisture(tiles);
+
+        let moisture = Grid::from_fn(tiles.width(), tiles.height(), |row, col| {
+            (0.4 * water_proximity[(row, col)] + 0.6 * wind_moisture[(row, col)]).clamp(0.0, 1.0)
+        });
+
+        Self {
+            temperature,
+            moisture,
+        }
+    }
+
+    // The equator runs through the middle row, the poles are the top and bottom rows.
+    // Some noise keeps the climate bands from following the rows exactly
+    fn give_temperature(tiles: &Grid<Tile>, seed: WorldSeed) -> Grid<f32> {
+        let last_row = (tiles.height() - 1).max(1) as f32;
+        let noise = Fractal::fbm(Simplex::new(seed.climate_seed()), 3).frequency(0.15);
+        Grid::from_fn(tiles.width(), tiles.height(), |row, col| {
+            let latitude = (row as f32 / last_row - 0.5).abs() * 2.0;
+            let altitude = tiles[(row, col)].altitude as f32;
+            let variation = noise.get(col as f32, row as f32) * CLIMATE_TEMPERATURE_NOISE;
+            (1.0 - latitude - altitude * CLIMATE_ALTITUDE_LAPSE + variation).clamp(0.0, 1.0)
+        })
+    }
+
+    // Breadth-first distance to the nearest water tile, mapped to an exponential falloff
+    fn give_water_proximity(tiles: &Grid<Tile>) -> Grid<f32> {
+        let mut distances = Grid::new(tiles.width(), tiles.height(), usize::MAX);
+        let mut queue = VecDeque::new();
+
+        for row in 0..tiles.height() {
+            for col in 0..tiles.width() {
+                if tiles[(row, col)].tile_type == TileType::WATER {
+                    distances[(row, col)] = 0;
+                    queue.push_back((row, col));
+                }
+            }
+        }
+
+        while let Some((row, col)) = queue.pop_front() {
+            let next_distance = distances[(row, col)] + 1;
+            for (dr, dc) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+                let (r, c) = (row as isize + dr, col as isize + dc);
+                if tiles.in_bounds(r, c) && distances[(r as usize, c as usize)] > next_distance {
+                    distances[(r as usize, c as usize)] = next_distance;
+                    queue.push_back((r as usize, c as usize));
+                }
+            }
+        }
+
+        Grid::from_fn(tiles.width(), tiles.height(), |row, col| {
+            match distances[(row, col)] {
+                usize::MAX => 0.0,
+                distance => (-(distance as f32) / CLIMATE_WATER_DISTANCE_DECAY).exp(),
+            }
+        })
+    }
+
+    // The prevailing wind blows from west to east. Air picks up humidity over water and rains
+    // it out over land, much faster where it is forced up a slope, which leaves a dry rain
+    // shadow on the lee side of mountain ranges.
+    fn give_wind_moisture(tiles: &Grid<Tile>) -> Grid<f32> {
+        let mut moisture = Grid::new(tiles.width(), tiles.height(), 0.0);
+
+        for row in 0..tiles.height() {
+            let mut air_humidity: f32 = 0.0;
+            let mut previous_altitude = 0.0;
+            for col in 0..tiles.width() {
+                let tile = tiles[(row, col)];
+                let altitude = tile.altitude as f32;
+
+                if tile.tile_type == TileType::WATER {
+                    air_humidity = (air_humidity + 0.5).min(1.0);
+                    moisture[(row, col)] = 1.0;
+                } else {
+                    moisture[(row, col)] = air_humidity;
+                    let lift = (altitude - previous_altitude).max(0.0);
+                    let rain = CLIMATE_BASE_RAIN + lift * CLIMATE_OROGRAPHIC_RAIN;
+                    air_humidity *= (1.0 - rain).max(0.0);
+                }
+                previous_altitude = altitude;
+            }
+        }
+        moisture
+    }
+
+    pub fn biome(&self, row: usize, col: usize) -> TileType {
+        let temperature = self.temperature[(row, col)];
+        if temperature < CLIMATE_SNOW_TEMPERATURE {
+            return TileType::SNOW;
+        }
+        let band = |value: f32, bands: &[f32; 3]| {
+            bands
+                .iter()
+                .position(|upper| value < *upper)
+                .unwrap_or(bands.len())
+        };
+        BIOME_TABLE[band(temperature, &TEMPERATURE_BANDS)]
+            [band(self.moisture[(row, col)], &MOISTURE_BANDS)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tile(tile_type: TileType, altitude: u8) -> Tile {
+        Tile {
+            tile_type,
+            altitude,
+            temperature: 0.0,
+            moisture: 0.0,
+            river_flow: 0.0,
+            river_direction: None,
+        }
+    }
+
+    // Sea along the west edge, flat lowland elsewhere
+    fn coast(width: usize, height: usize) -> Grid<Tile> {
+        Grid::from_fn(width, height, |_, col| match col {
+            0..=2 => tile(TileType::WATER, 0),
+            _ => tile(TileType::GRASS, 1),
+        })
+    }
+
+    #[test]
+    fn fields_are_normalized_and_the_equator_is_warmest() {
+        let tiles = coast(12, 21);
+        let climate = Climate::new(&tiles, WorldSeed::new(3));
+
+        for value in climate.temperature.iter().chain(climate.moisture.iter()) {
+            assert!((0.0..=1.0).contains(value), "{value} out of 0..=1");
+        }
+        for col in 0..12 {
+            // The noise is smaller than the gap between the equator and a pole
+            assert!(climate.temperature[(10, col)] > climate.temperature[(0, col)]);
+            assert!(climate.temperature[(10, col)] > climate.temperature[(20, col)]);
+        }
+    }
+
+    #[test]
+    fn mountains_leave_a_dry_lee_side() {
+        let flat = coast(16, 1);
+        let mut ridge = flat.clone();
+        for col in 7..=8 {
+            ridge[(0, col)] = tile(TileType::MOUNTAINS, 120);
+        }
+
+        let flat_moisture = Climate::give_wind_moisture(&flat);
+        let ridge_moisture = Climate::give_wind_moisture(&ridge);
+        for col in 9..16 {
+            assert!(ridge_moisture[(0, col)] < flat_moisture[(0, col)]);
+        }
+        // Downwind of the sea the air only ever dries out over land
+        for col in 4..16 {
+            assert!(flat_moisture[(0, col)] <= flat_moisture[(0, col - 1)]);
+        }
+    }
+
+    #[test]
+    fn biome_follows_the_whittaker_table() {
+        let climate = Climate {
+            temperature: Grid::from_fn(3, 1, |_, col| [0.05, 0.9, 0.9][col]),
+            moisture: Grid::from_fn(3, 1, |_, col| [1.0, 0.1, 0.95][col]),
+        };
+        assert_eq!(climate.biome(0, 0), TileType::SNOW);
+        assert_eq!(climate.biome(0, 1), TileType::DESERT);
+        assert_eq!(climate.biome(0, 2), TileType::JUNGLE);
+    }
+}
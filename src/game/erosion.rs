@@ -0,0 +1,250 @@
+use rand::Rng;
+
+use crate::game::grid::Grid;
+use crate::game::seed::WorldSeed;
+
+/// Particle-based hydraulic erosion: droplets run down the heightfield, picking up sediment
+/// where they speed up and dropping it where they slow down or evaporate.
+///
+/// Works on a copy of the heightfield normalized to `0.0..=1.0`, so the same parameters give
+/// similar results whatever the height range of the input is.
+pub struct HydraulicErosion {
+    pub droplets: usize,
+    pub max_lifetime: usize,
+    pub inertia: f32,
+    pub capacity: f32,
+    pub min_capacity: f32,
+    pub erode_speed: f32,
+    pub deposit_speed: f32,
+    pub evaporate_speed: f32,
+    pub gravity: f32,
+}
+
+/// Thermal erosion: material slides down to lower neighbours wherever the height difference
+/// is steeper than the talus, given in heightfield units per cell.
+pub struct ThermalErosion {
+    pub iterations: usize,
+    pub talus: f32,
+    pub strength: f32,
+}
+
+impl HydraulicErosion {
+    pub fn new(droplets: usize) -> Self {
+        Self {
+            droplets,
+            max_lifetime: 30,
+            inertia: 0.05,
+            capacity: 4.0,
+            min_capacity: 0.01,
+            erode_speed: 0.3,
+            deposit_speed: 0.3,
+            evaporate_speed: 0.01,
+            gravity: 4.0,
+        }
+    }
+
+    pub fn erode(&self, heights: &mut Grid<f32>, seed: WorldSeed) {
+        let (width, height) = (heights.width(), heights.height());
+        if width < 2 || height < 2 {
+            return;
+        }
+        let min = heights.iter().copied().fold(f32::INFINITY, f32::min);
+        let max = heights.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        let range = max - min;
+        if range <= 0.0 {
+            return;
+        }
+        let mut map = Grid::from_fn(width, height, |row, col| {
+            (heights[(row, col)] - min) / range
+        });
+
+        let mut rng = seed.rng();
+        let max_x = (width - 1) as f32;
+        let max_y = (height - 1) as f32;
+
+        for _ in 0..self.droplets {
+            let mut pos = (rng.random_range(0.0..max_x), rng.random_range(0.0..max_y));
+            let mut dir = (0.0, 0.0);
+            let mut speed = 1.0;
+            let mut water = 1.0;
+            let mut sediment = 0.0;
+
+            for _ in 0..self.max_lifetime {
+                let cell = (pos.0 as usize, pos.1 as usize);
+                let offset = (pos.0 - cell.0 as f32, pos.1 - cell.1 as f32);
+                let (old_height, gradient) = Self::height_and_gradient(&map, pos);
+
+                dir = (
+                    dir.0 * self.inertia - gradient.0 * (1.0 - self.inertia),
+                    dir.1 * self.inertia - gradient.1 * (1.0 - self.inertia),
+                );
+                let len = f32::sqrt(dir.0 * dir.0 + dir.1 * dir.1);
+                if len <= f32::EPSILON {
+                    break;
+                }
+                dir = (dir.0 / len, dir.1 / len);
+                pos = (pos.0 + dir.0, pos.1 + dir.1);
+                if pos.0 < 0.0 || pos.1 < 0.0 || pos.0 >= max_x || pos.1 >= max_y {
+                    break;
+                }
+
+                let delta_height = Self::height_and_gradient(&map, pos).0 - old_height;
+                let capacity = f32::max(
+                    -delta_height * speed * water * self.capacity,
+                    self.min_capacity,
+                );
+
+                if sediment > capacity || delta_height > 0.0 {
+                    // Fill the pit the droplet just left, or drop the excess sediment
+                    let deposit = if delta_height > 0.0 {
+                        f32::min(delta_height, sediment)
+                    } else {
+                        (sediment - capacity) * self.deposit_speed
+                    };
+                    sediment -= deposit;
+                    Self::spread(&mut map, cell, offset, deposit);
+                } else {
+                    let erode = f32::min((capacity - sediment) * self.erode_speed, -delta_height);
+                    Self::spread(&mut map, cell, offset, -erode);
+                    sediment += erode;
+                }
+
+                speed = f32::sqrt(f32::max(speed * speed - delta_height * self.gravity, 0.0));
+                water *= 1.0 - self.evaporate_speed;
+            }
+        }
+
+        // Deposits can overshoot a peak or a pit floor slightly, keep the original range
+        for row in 0..height {
+            for col in 0..width {
+                heights[(row, col)] = map[(row, col)].clamp(0.0, 1.0) * range + min;
+            }
+        }
+    }
+
+    // Bilinear height and gradient at a position given as (col, row)
+    fn height_and_gradient(map: &Grid<f32>, pos: (f32, f32)) -> (f32, (f32, f32)) {
+        let (col, row) = (pos.0 as usize, pos.1 as usize);
+        let (x, y) = (pos.0 - col as f32, pos.1 - row as f32);
+
+        let tl = map[(row, col)];
+        let tr = map[(row, col + 1)];
+        let bl = map[(row + 1, col)];
+        let br = map[(row + 1, col + 1)];
+
+        let gradient_x = (tr - tl) * (1.0 - y) + (br - bl) * y;
+        let gradient_y = (bl - tl) * (1.0 - x) + (br - tr) * x;
+        let height =
+            tl * (1.0 - x) * (1.0 - y) + tr * x * (1.0 - y) + bl * (1.0 - x) * y + br * x * y;
+        (height, (gradient_x, gradient_y))
+    }
+
+    fn spread(map: &mut Grid<f32>, cell: (usize, usize), offset: (f32, f32), amount: f32) {
+        let (col, row) = cell;
+        let (x, y) = offset;
+        map[(row, col)] += amount * (1.0 - x) * (1.0 - y);
+        map[(row, col + 1)] += amount * x * (1.0 - y);
+        map[(row + 1, col)] += amount * (1.0 - x) * y;
+        map[(row + 1, col + 1)] += amount * x * y;
+    }
+}
+
+impl ThermalErosion {
+    pub fn new(iterations: usize, talus: f32) -> Self {
+        Self {
+            iterations,
+            talus,
+            strength: 0.5,
+        }
+    }
+
+    pub fn erode(&self, heights: &mut Grid<f32>) {
+        let (width, height) = (heights.width(), heights.height());
+        let mut deltas = Grid::new(width, height, 0.0);
+
+        for _ in 0..self.iterations {
+            for row in 0..height {
+                for col in 0..width {
+                    let current = heights[(row, col)];
+                    for (dr, dc) in [(0, 1), (1, 0), (0, -1), (-1, 0)] {
+                        let (r, c) = (row as isize + dr, col as isize + dc);
+                        if !heights.in_bounds(r, c) {
+                            continue;
+                        }
+                        let (r, c) = (r as usize, c as usize);
+                        let difference = current - heights[(r, c)];
+                        if difference > self.talus {
+                            // A quarter per neighbour so a cell never gives away more than it has
+                            let moved = self.strength * (difference - self.talus) / 4.0;
+                            deltas[(row, col)] -= moved;
+                            deltas[(r, c)] += moved;
+                        }
+                    }
+                }
+            }
+            for row in 0..height {
+                for col in 0..width {
+                    heights[(row, col)] += deltas[(row, col)];
+                    deltas[(row, col)] = 0.0;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::world_map::WorldMap;
+
+    fn total(heights: &Grid<f32>) -> f64 {
+        heights.iter().map(|&height| height as f64).sum()
+    }
+
+    fn range(heights: &Grid<f32>) -> (f32, f32) {
+        let min = heights.iter().copied().fold(f32::INFINITY, f32::min);
+        let max = heights.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        (min, max)
+    }
+
+    #[test]
+    fn thermal_erosion_conserves_material_and_flattens_slopes() {
+        let mut heights = WorldMap::diamond_square(WorldSeed::new(2), 33, 33);
+        let before = total(&heights);
+        let steepest = |heights: &Grid<f32>| {
+            let mut steepest: f32 = 0.0;
+            for row in 0..33 {
+                for col in 1..33 {
+                    steepest = steepest.max((heights[(row, col)] - heights[(row, col - 1)]).abs());
+                }
+            }
+            steepest
+        };
+        let steepest_before = steepest(&heights);
+
+        ThermalErosion::new(20, 2.0).erode(&mut heights);
+        assert!((total(&heights) - before).abs() < 1e-3 * before.abs().max(1.0));
+        assert!(steepest(&heights) < steepest_before);
+    }
+
+    #[test]
+    fn hydraulic_erosion_is_deterministic_and_stays_in_range() {
+        let original = WorldMap::diamond_square(WorldSeed::new(4), 33, 33);
+        let (min, max) = range(&original);
+
+        let mut heights = original.clone();
+        HydraulicErosion::new(2000).erode(&mut heights, WorldSeed::new(9));
+        let mut again = original.clone();
+        HydraulicErosion::new(2000).erode(&mut again, WorldSeed::new(9));
+        let mut other = original.clone();
+        HydraulicErosion::new(2000).erode(&mut other, WorldSeed::new(10));
+
+        assert_eq!(heights, again);
+        assert_ne!(heights, other);
+        assert_ne!(heights, original);
+        // Droplets only put back what they picked up, some of it leaves the map with them
+        assert!(total(&heights) <= total(&original) + 1e-3);
+        let (eroded_min, eroded_max) = range(&heights);
+        assert!(eroded_min >= min && eroded_max <= max);
+    }
+}
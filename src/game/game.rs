@@ -1,9 +1,18 @@
-use crate::constants;
-use crate::constants::{CHUNK_MAP_COLS, CHUNK_MAP_ROWS};
-use crate::game::character::Character;
-use crate::game::chunk_map::ChunkMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::constants::{
+    CHARACTER_MAX_SLOPE, CHARACTER_WADING_SPEED, CHUNK_MAP_COLS, CHUNK_MAP_ROWS,
+};
+use crate::game::character::{Character, CharacterMovement};
+use crate::game::chunk_manager::{ChunkCoord, ChunkManager};
+use crate::game::chunk_map::{ChunkMap, ChunkTileType};
+use crate::game::save::{self, SaveData};
+use crate::game::seed::WorldSeed;
 use crate::game::world_map;
-use crate::input::CharacterMovement;
 
 #[derive(PartialEq, Debug)]
 pub enum GameState {
@@ -11,95 +20,413 @@ pub enum GameState {
     DEAD,
 }
 
-#[derive(Copy, Clone)]
+/// Snapshot of what the renderer needs, taken at the end of the tick `tick`.
+#[derive(Clone)]
 pub struct GameExports {
+    pub tick: u64,
+    pub timestamp: Instant,
     pub character: [f32; 3],
+    pub chunks: Vec<(ChunkCoord, Arc<ChunkMap>)>,
 }
 
 impl GameExports {
-    pub fn new() -> Self {
+    /// Blends two snapshots, `alpha` going from `previous` at 0.0 to `current` at 1.0.
+    /// Anything that can't be blended, like the loaded chunks, comes from `current`.
+    pub fn interpolate(previous: &Self, current: &Self, alpha: f32) -> Self {
+        let alpha = alpha.clamp(0.0, 1.0);
+        let lerp = |a: f32, b: f32| a + (b - a) * alpha;
         Self {
-            character: [0.0, 0.0, 0.0],
+            tick: current.tick,
+            timestamp: current.timestamp,
+            character: [
+                lerp(previous.character[0], current.character[0]),
+                lerp(previous.character[1], current.character[1]),
+                lerp(previous.character[2], current.character[2]),
+            ],
+            chunks: current.chunks.clone(),
         }
     }
 }
 
-pub struct GameForInit<'a> {
+pub struct GameForInit {
     pub character_pos: [f32; 3],
-    pub world_map: &'a [[world_map::Tile; constants::WORLD_MAP_COLS]; constants::WORLD_MAP_ROWS],
-    pub chunk_map: &'a ChunkMap,
+    pub world_map: Arc<world_map::WorldMap>,
+}
+
+/// What the frontend can ask of the game thread.
+pub enum GameCommand {
+    MOVE(CharacterMovement),
+    TERMINATE,
 }
 
 pub struct Game {
     pub game_state: GameState,
-    world_map: world_map::WorldMap,
-    chunk_map: ChunkMap,
+    tick: u64,
+    seed: WorldSeed,
+    world_map: Arc<world_map::WorldMap>,
+    chunk_manager: ChunkManager,
     character: Character,
 }
 
 impl Game {
-    pub fn new() -> Self {
+    pub fn new(seed: WorldSeed, world_width: usize, world_height: usize) -> Self {
+        let world_map = Arc::new(world_map::WorldMap::new(
+            seed.world_map_seed(),
+            world_width,
+            world_height,
+        ));
+        let (start_row, start_col) = Self::start_tile(&world_map);
+        let start_coord = ChunkCoord::new(start_row as i32, start_col as i32);
+        let chunk_manager = ChunkManager::new(Arc::clone(&world_map), seed, start_coord);
+
+        let [origin_x, origin_y] = start_coord.origin();
+        let start_x = origin_x + CHUNK_MAP_COLS as f32 / 2.0;
+        let start_y = origin_y + CHUNK_MAP_ROWS as f32 / 2.0;
+        let start_altitude = chunk_manager.height_at(start_x, start_y).unwrap_or(0.0);
+
+        Game {
+            game_state: GameState::RUNNING,
+            tick: 0,
+            seed,
+            world_map,
+            chunk_manager,
+            character: Character::new([start_x, start_y, start_altitude]),
+        }
+    }
+
+    /// Writes the whole game state to `path`, see `save::encode` for the format.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, save::encode(&self.save_data()))
+    }
+
+    /// Restores a game written by `save`, from this version or an older one.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::from_save_data(save::decode(&fs::read(path)?)?))
+    }
+
+    pub fn save_data(&self) -> SaveData {
+        SaveData {
+            seed: self.seed,
+            tick: self.tick,
+            world_map: Arc::clone(&self.world_map),
+            chunks: self.chunk_manager.loaded_chunks(),
+            character_position: self.character.position,
+        }
+    }
+
+    fn from_save_data(data: SaveData) -> Self {
+        let [x, y, _] = data.character_position;
+        let chunk_manager = ChunkManager::with_chunks(
+            Arc::clone(&data.world_map),
+            data.seed,
+            ChunkCoord::from_position(x, y),
+            data.chunks,
+        );
         Game {
             game_state: GameState::RUNNING,
-            world_map: world_map::WorldMap::new(),
-            chunk_map: ChunkMap::new(),
-            character: Character::new([0.0, 0.0, 0.0]),
+            tick: data.tick,
+            seed: data.seed,
+            world_map: data.world_map,
+            chunk_manager,
+            character: Character::new(data.character_position),
         }
     }
 
+    // Land tile closest to the center of the world map, or the center itself if it's all water
+    fn start_tile(world_map: &world_map::WorldMap) -> (usize, usize) {
+        let tiles = &world_map.tiles;
+        let center = (tiles.height() / 2, tiles.width() / 2);
+        let mut best = None;
+        for row in 0..tiles.height() {
+            for col in 0..tiles.width() {
+                if matches!(
+                    tiles[(row, col)].tile_type,
+                    world_map::TileType::WATER | world_map::TileType::LAKE
+                ) {
+                    continue;
+                }
+                let distance = row.abs_diff(center.0).pow(2) + col.abs_diff(center.1).pow(2);
+                if best.is_none_or(|(best_distance, _)| distance < best_distance) {
+                    best = Some((distance, (row, col)));
+                }
+            }
+        }
+        best.map_or(center, |(_, tile)| tile)
+    }
+
     pub fn terminate(&mut self) {
         self.game_state = GameState::DEAD;
     }
 
-    pub fn update(&mut self, character_movement: CharacterMovement) -> GameState {
-        self.step_character_movement(character_movement);
+    /// Advances the game by `dt` seconds.
+    pub fn update(&mut self, character_movement: CharacterMovement, dt: f32) -> GameState {
+        self.tick += 1;
+        self.step_character_movement(character_movement, dt);
+        self.chunk_manager.update(self.character.position);
         match self.game_state {
             GameState::RUNNING => GameState::RUNNING,
             GameState::DEAD => GameState::DEAD,
         }
     }
 
+    /// Waits for the chunks being generated, see `ChunkManager::finish_pending`.
+    pub fn finish_loading(&mut self) {
+        self.chunk_manager.finish_pending();
+    }
+
     pub fn get_exports(&self) -> GameExports {
         GameExports {
+            tick: self.tick,
+            timestamp: Instant::now(),
             character: self.character.position,
+            chunks: self.chunk_manager.loaded_chunks(),
         }
     }
 
     pub fn get_for_init(&self) -> GameForInit {
         GameForInit {
             character_pos: self.character.position,
-            world_map: &self.world_map.tiles,
-            chunk_map: &self.chunk_map,
+            world_map: Arc::clone(&self.world_map),
         }
     }
 
-    pub fn step_character_movement(&mut self, character_movement: CharacterMovement) {
+    pub fn step_character_movement(&mut self, character_movement: CharacterMovement, dt: f32) {
         if character_movement.moving {
-            let speed = self.character.speed;
+            let [x, y, _] = self.character.position;
+            let speed = self.character.speed * self.speed_modifier(x, y) * dt;
             let [dx, dy] = character_movement.direction;
-            let target_position_x = self.character.position[0] + speed * dx;
-            let target_position_y = self.character.position[1] + speed * dy;
-
-            self.character.position[0] = target_position_x;
-            self.character.position[1] = target_position_y;
-
-            let map_index_row = target_position_y + CHUNK_MAP_ROWS as f32 / 2 as f32;
-            let map_index_col = target_position_x + CHUNK_MAP_COLS as f32 / 2 as f32;
-
-            if map_index_row > 0.0
-                && map_index_row < CHUNK_MAP_ROWS as f32
-                && map_index_col > 0.0
-                && map_index_col < CHUNK_MAP_COLS as f32
-            {
-                let altitude =
-                    self.chunk_map.altitudes[map_index_row as usize][map_index_col as usize];
-                self.character.position[2] = altitude;
+            let (step_x, step_y) = (speed * dx, speed * dy);
+
+            // When the full step is blocked, try each axis on its own to slide along the edge
+            let candidates = [[x + step_x, y + step_y], [x + step_x, y], [x, y + step_y]];
+            let Some((target, height)) = candidates
+                .into_iter()
+                .find_map(|target| self.walkable_height(target).map(|height| (target, height)))
+            else {
+                return;
+            };
+
+            self.character.position = [target[0], target[1], height];
+        }
+    }
+
+    // Ground height at the target if the character can step there: its chunk is loaded, it
+    // doesn't lead into deep water, and it isn't a climb onto a slope steeper than the limit.
+    // A character already in deep water may move, or it couldn't get out
+    fn walkable_height(&self, target: [f32; 2]) -> Option<f32> {
+        let [x, y] = target;
+        let [current_x, current_y, _] = self.character.position;
+        let into_deep_water = self.chunk_manager.tile_type_at(x, y)? == ChunkTileType::DEEP_WATER
+            && self.chunk_manager.tile_type_at(current_x, current_y)
+                != Some(ChunkTileType::DEEP_WATER);
+        if into_deep_water {
+            return None;
+        }
+        let height = self.chunk_manager.height_at(x, y)?;
+        let normal = self.chunk_manager.normal_at(x, y)?;
+        let slope = normal[2].clamp(-1.0, 1.0).acos().to_degrees();
+        if slope > CHARACTER_MAX_SLOPE && height > self.character.position[2] {
+            return None;
+        }
+        Some(height)
+    }
+
+    fn speed_modifier(&self, x: f32, y: f32) -> f32 {
+        match self.chunk_manager.tile_type_at(x, y) {
+            Some(ChunkTileType::SHALLOW_WATER) => CHARACTER_WADING_SPEED,
+            _ => 1.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::grid::Grid;
+    use crate::game::world_map::{Tile, TileType, WorldMap};
+
+    fn tile(tile_type: TileType) -> Tile {
+        Tile {
+            tile_type,
+            altitude: 0,
+            temperature: 0.5,
+            moisture: 0.5,
+            river_flow: 0.0,
+            river_direction: None,
+        }
+    }
+
+    #[test]
+    fn start_tile_skips_sea_and_lakes() {
+        let mut tiles = Grid::new(7, 7, tile(TileType::WATER));
+        tiles[(3, 3)] = tile(TileType::LAKE);
+        tiles[(3, 4)] = tile(TileType::LAKE);
+        tiles[(1, 5)] = tile(TileType::GRASS);
+        let world = WorldMap { tiles };
+        assert_eq!(Game::start_tile(&world), (1, 5));
+    }
+
+    // Flat grass chunk at height 0, the terrain of the tests is carved out of it
+    fn flat_chunk() -> ChunkMap {
+        ChunkMap {
+            tile_types: Grid::new(CHUNK_MAP_COLS, CHUNK_MAP_ROWS, ChunkTileType::GRASS),
+            edges: Grid::new(CHUNK_MAP_COLS + 1, CHUNK_MAP_ROWS + 1, 0.0),
+            normals: Grid::new(CHUNK_MAP_COLS + 1, CHUNK_MAP_ROWS + 1, [0.0, 0.0, 1.0]),
+        }
+    }
+
+    // Chunk tiles from column `col` on get `tile_type`
+    fn with_tiles_from(mut chunk: ChunkMap, col: usize, tile_type: ChunkTileType) -> ChunkMap {
+        for row in 0..CHUNK_MAP_ROWS {
+            for tile_col in col..CHUNK_MAP_COLS {
+                chunk.tile_types[(row, tile_col)] = tile_type;
+            }
+        }
+        chunk
+    }
+
+    // Every chunk around the character is `chunk`, which starts at `position` on its surface
+    fn game_on(chunk: ChunkMap, position: [f32; 2]) -> Game {
+        let seed = WorldSeed::new(1);
+        let world_map = Arc::new(WorldMap {
+            tiles: Grid::new(3, 3, tile(TileType::GRASS)),
+        });
+        let chunk = Arc::new(chunk);
+        let center = ChunkCoord::new(0, 0);
+        let mut chunks = Vec::new();
+        for row in -1..=1 {
+            for col in -1..=1 {
+                chunks.push((ChunkCoord::new(row, col), Arc::clone(&chunk)));
+            }
+        }
+        let chunk_manager = ChunkManager::with_chunks(Arc::clone(&world_map), seed, center, chunks);
+        let height = chunk.height_at(position[0], position[1]);
+        Game {
+            game_state: GameState::RUNNING,
+            tick: 0,
+            seed,
+            world_map,
+            chunk_manager,
+            character: Character::new([position[0], position[1], height]),
+        }
+    }
+
+    fn walk(game: &mut Game, direction: [f32; 2], dt: f32) -> [f32; 3] {
+        let movement = CharacterMovement {
+            moving: true,
+            direction,
+        };
+        game.step_character_movement(movement, dt);
+        game.character.position
+    }
+
+    #[test]
+    fn character_walks_at_its_speed_on_flat_ground() {
+        let mut game = game_on(flat_chunk(), [50.5, 50.5]);
+        let speed = game.character.speed;
+        let [x, y, z] = walk(&mut game, [1.0, 0.0], 0.1);
+        assert!((x - (50.5 + speed * 0.1)).abs() < 1e-4);
+        assert_eq!((y, z), (50.5, 0.0));
+
+        let idle = CharacterMovement::new();
+        game.step_character_movement(idle, 0.1);
+        assert_eq!(game.character.position, [x, y, z]);
+    }
+
+    #[test]
+    fn shallow_water_slows_the_character_down() {
+        let chunk = with_tiles_from(flat_chunk(), 0, ChunkTileType::SHALLOW_WATER);
+        let mut game = game_on(chunk, [50.5, 50.5]);
+        let speed = game.character.speed;
+        let [x, _, _] = walk(&mut game, [1.0, 0.0], 0.1);
+        assert!((x - (50.5 + speed * CHARACTER_WADING_SPEED * 0.1)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn deep_water_blocks_the_way_in_but_not_out() {
+        let chunk = with_tiles_from(flat_chunk(), 52, ChunkTileType::DEEP_WATER);
+        let mut game = game_on(chunk, [50.5, 50.5]);
+        for _ in 0..10 {
+            walk(&mut game, [1.0, 0.0], 0.1);
+        }
+        assert!(game.character.position[0] < 52.0);
+        assert!(game.character.position[0] > 51.0);
+
+        // Already in deep water, e.g. after a load, it can still swim back out
+        let chunk = with_tiles_from(flat_chunk(), 52, ChunkTileType::DEEP_WATER);
+        let mut game = game_on(chunk, [55.5, 50.5]);
+        let [x, _, _] = walk(&mut game, [-1.0, 0.0], 0.1);
+        assert!(x < 55.5);
+    }
+
+    // A 63 degrees ramp going up along +x from column 52
+    fn ramp_chunk() -> ChunkMap {
+        let mut chunk = flat_chunk();
+        let steep = [-2.0 / 5.0_f32.sqrt(), 0.0, 1.0 / 5.0_f32.sqrt()];
+        for row in 0..=CHUNK_MAP_ROWS {
+            for col in 52..=CHUNK_MAP_COLS {
+                chunk.edges[(row, col)] = (col - 52) as f32 * 2.0;
+                chunk.normals[(row, col)] = steep;
             }
+        }
+        chunk
+    }
+
+    #[test]
+    fn steep_slopes_block_climbing_but_not_descending() {
+        let mut game = game_on(ramp_chunk(), [50.5, 50.5]);
+        for _ in 0..10 {
+            walk(&mut game, [1.0, 0.0], 0.1);
+        }
+        assert!(game.character.position[0] < 53.0);
+
+        let mut game = game_on(ramp_chunk(), [60.5, 50.5]);
+        let [_, _, start_z] = game.character.position;
+        let [x, _, z] = walk(&mut game, [-1.0, 0.0], 0.1);
+        assert!(x < 60.5 && z < start_z);
+    }
+
+    #[test]
+    fn blocked_diagonal_steps_slide_along_the_edge() {
+        let chunk = with_tiles_from(flat_chunk(), 52, ChunkTileType::DEEP_WATER);
+        let mut game = game_on(chunk, [51.5, 50.5]);
+        let diagonal = std::f32::consts::FRAC_1_SQRT_2;
+        let [x, y, _] = walk(&mut game, [diagonal, diagonal], 0.1);
+        assert_eq!(x, 51.5);
+        assert!(y > 50.5);
+    }
+
+    #[test]
+    fn exports_blend_the_character_and_keep_the_rest_current() {
+        let start = Instant::now();
+        let chunk = Arc::new(flat_chunk());
+        let previous = GameExports {
+            tick: 4,
+            timestamp: start,
+            character: [10.0, 20.0, 1.0],
+            chunks: Vec::new(),
+        };
+        let current = GameExports {
+            tick: 5,
+            timestamp: start + std::time::Duration::from_millis(8),
+            character: [12.0, 16.0, 3.0],
+            chunks: vec![(ChunkCoord::new(1, 2), Arc::clone(&chunk))],
+        };
+
+        let blend = |alpha| GameExports::interpolate(&previous, &current, alpha).character;
+        assert_eq!(blend(0.0), previous.character);
+        assert_eq!(blend(1.0), current.character);
+        assert_eq!(blend(0.5), [11.0, 18.0, 2.0]);
+        assert_eq!(blend(-1.0), previous.character);
+        assert_eq!(blend(2.0), current.character);
 
-            println!(
-                "Target position [{},{}]",
-                target_position_x, target_position_y
-            )
+        for alpha in [0.0, 0.5, 1.0] {
+            let blended = GameExports::interpolate(&previous, &current, alpha);
+            assert_eq!(blended.tick, current.tick);
+            assert_eq!(blended.timestamp, current.timestamp);
+            assert_eq!(blended.chunks.len(), 1);
+            assert_eq!(blended.chunks[0].0, ChunkCoord::new(1, 2));
+            assert!(Arc::ptr_eq(&blended.chunks[0].1, &chunk));
         }
     }
 }
@@ -0,0 +1,149 @@
+use std::ops::{Index, IndexMut};
+
+/// Heap-backed 2D grid with a runtime size, indexed by `(row, col)`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Grid<T> {
+    width: usize,
+    height: usize,
+    cells: Vec<T>,
+}
+
+impl<T: Clone> Grid<T> {
+    pub fn new(width: usize, height: usize, value: T) -> Self {
+        Self {
+            width,
+            height,
+            cells: vec![value; width * height],
+        }
+    }
+}
+
+impl<T> Grid<T> {
+    pub fn from_fn(width: usize, height: usize, mut f: impl FnMut(usize, usize) -> T) -> Self {
+        let mut cells = Vec::with_capacity(width * height);
+        for row in 0..height {
+            for col in 0..width {
+                cells.push(f(row, col));
+            }
+        }
+        Self {
+            width,
+            height,
+            cells,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.cells.iter()
+    }
+
+    pub fn in_bounds(&self, row: isize, col: isize) -> bool {
+        row >= 0 && col >= 0 && (row as usize) < self.height && (col as usize) < self.width
+    }
+}
+
+impl<T> Index<(usize, usize)> for Grid<T> {
+    type Output = T;
+
+    fn index(&self, (row, col): (usize, usize)) -> &T {
+        assert!(
+            row < self.height && col < self.width,
+            "({row}, {col}) out of a {}x{} grid",
+            self.height,
+            self.width
+        );
+        &self.cells[row * self.width + col]
+    }
+}
+
+impl<T> IndexMut<(usize, usize)> for Grid<T> {
+    fn index_mut(&mut self, (row, col): (usize, usize)) -> &mut T {
+        assert!(
+            row < self.height && col < self.width,
+            "({row}, {col}) out of a {}x{} grid",
+            self.height,
+            self.width
+        );
+        &mut self.cells[row * self.width + col]
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Direction {
+    N,
+    NE,
+    E,
+    SE,
+    S,
+    SW,
+    W,
+    NW,
+}
+
+impl Direction {
+    pub const ALL: [Direction; 8] = [
+        Direction::N,
+        Direction::NE,
+        Direction::E,
+        Direction::SE,
+        Direction::S,
+        Direction::SW,
+        Direction::W,
+        Direction::NW,
+    ];
+
+    /// `(row, col)` step, north being the row above.
+    pub fn offset(&self) -> (isize, isize) {
+        match self {
+            Direction::N => (-1, 0),
+            Direction::NE => (-1, 1),
+            Direction::E => (0, 1),
+            Direction::SE => (1, 1),
+            Direction::S => (1, 0),
+            Direction::SW => (1, -1),
+            Direction::W => (0, -1),
+            Direction::NW => (-1, -1),
+        }
+    }
+
+    pub fn opposite(&self) -> Direction {
+        Direction::ALL[(*self as usize + 4) % 8]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cells_are_stored_by_row() {
+        let grid = Grid::from_fn(3, 2, |row, col| row * 10 + col);
+        assert_eq!((grid.width(), grid.height()), (3, 2));
+        assert_eq!(grid[(1, 2)], 12);
+        assert!(grid.in_bounds(1, 2));
+        assert!(!grid.in_bounds(2, 0) && !grid.in_bounds(0, 3) && !grid.in_bounds(-1, 0));
+    }
+
+    #[test]
+    #[should_panic(expected = "out of a 2x3 grid")]
+    fn column_past_the_width_panics_instead_of_wrapping() {
+        let grid = Grid::from_fn(3, 2, |row, col| row * 10 + col);
+        // Row 0, col 3 would be row 1, col 0 without the check
+        let _ = grid[(0, 3)];
+    }
+
+    #[test]
+    #[should_panic(expected = "out of a 2x3 grid")]
+    fn mutable_access_out_of_bounds_panics() {
+        let mut grid = Grid::new(3, 2, 0);
+        grid[(2, 0)] = 1;
+    }
+}
@@ -0,0 +1,257 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use crate::constants::{LAKE_MIN_DEPTH, RIVER_MIN_FLOW, RIVER_SOURCE_ALTITUDE};
+use crate::game::grid::{Direction, Grid};
+use crate::game::world_map::{Tile, TileType};
+
+/// Drainage of the world map: every land tile flows towards the sea along `direction`,
+/// carrying the rain collected upstream in `flow`.
+pub struct Hydrology {
+    pub filled: Grid<f32>,
+    pub direction: Grid<Option<Direction>>,
+    pub flow: Grid<f32>,
+    pub river: Grid<bool>,
+}
+
+struct Flood {
+    filled: Grid<f32>,
+    direction: Grid<Option<Direction>>,
+    // Cells in the order they were reached, downstream cells always come first
+    order: Vec<(usize, usize)>,
+}
+
+struct FloodCell {
+    height: f32,
+    order: usize,
+    row: usize,
+    col: usize,
+}
+
+// Reversed so that BinaryHeap pops the lowest cell first, ties in insertion order
+impl Ord for FloodCell {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .height
+            .total_cmp(&self.height)
+            .then(other.order.cmp(&self.order))
+    }
+}
+
+impl PartialOrd for FloodCell {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for FloodCell {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for FloodCell {}
+
+impl Hydrology {
+    pub fn new(altitudes: &Grid<f32>, tiles: &Grid<Tile>) -> Self {
+        let flood = Self::priority_flood(altitudes, tiles);
+        let (flow, river) = Self::give_flow(tiles, &flood);
+
+        Self {
+            filled: flood.filled,
+            direction: flood.direction,
+            flow,
+            river,
+        }
+    }
+
+    pub fn is_lake(&self, altitudes: &Grid<f32>, row: usize, col: usize) -> bool {
+        self.filled[(row, col)] - altitudes[(row, col)].max(0.0) > LAKE_MIN_DEPTH
+    }
+
+    // Floods the heightmap inwards from the sea, lowest cell first. Each land cell drains
+    // into the neighbour it was reached from and is raised to at least that neighbour's
+    // level, which fills every depression up to its spill point.
+    fn priority_flood(altitudes: &Grid<f32>, tiles: &Grid<Tile>) -> Flood {
+        let (width, height) = (tiles.width(), tiles.height());
+        let mut filled = Grid::new(width, height, f32::NAN);
+        let mut direction = Grid::new(width, height, None);
+        let mut visited = Grid::new(width, height, false);
+        let mut flood_order = Vec::with_capacity(width * height);
+        let mut queue = BinaryHeap::new();
+        let mut order = 0;
+
+        let is_outlet = |row: usize, col: usize| tiles[(row, col)].tile_type == TileType::WATER;
+        let has_sea = tiles.iter().any(|tile| tile.tile_type == TileType::WATER);
+
+        for row in 0..height {
+            for col in 0..width {
+                let on_border = row == 0 || col == 0 || row == height - 1 || col == width - 1;
+                if (has_sea && is_outlet(row, col)) || (!has_sea && on_border) {
+                    let land_height = altitudes[(row, col)];
+                    filled[(row, col)] = land_height;
+                    visited[(row, col)] = true;
+                    queue.push(FloodCell {
+                        height: land_height,
+                        order,
+                        row,
+                        col,
+                    });
+                    order += 1;
+                }
+            }
+        }
+
+        while let Some(cell) = queue.pop() {
+            flood_order.push((cell.row, cell.col));
+            for neighbour in Direction::ALL {
+                let (dr, dc) = neighbour.offset();
+                let (r, c) = (cell.row as isize + dr, cell.col as isize + dc);
+                if !tiles.in_bounds(r, c) || visited[(r as usize, c as usize)] {
+                    continue;
+                }
+                let (r, c) = (r as usize, c as usize);
+                let land_height = altitudes[(r, c)].max(0.0).max(cell.height);
+                visited[(r, c)] = true;
+                filled[(r, c)] = land_height;
+                direction[(r, c)] = Some(neighbour.opposite());
+                queue.push(FloodCell {
+                    height: land_height,
+                    order,
+                    row: r,
+                    col: c,
+                });
+                order += 1;
+            }
+        }
+        Flood {
+            filled,
+            direction,
+            order: flood_order,
+        }
+    }
+
+    // Walks the drainage tree from the leaves down, accumulating rainfall. Only water that
+    // has passed through the MOUNTAINS or HIGHLANDS altitude band is allowed to form a river,
+    // whether or not those tiles ended up covered in snow.
+    fn give_flow(tiles: &Grid<Tile>, flood: &Flood) -> (Grid<f32>, Grid<bool>) {
+        let (width, height) = (tiles.width(), tiles.height());
+        let mut flow = Grid::from_fn(width, height, |row, col| {
+            let tile = tiles[(row, col)];
+            match tile.tile_type {
+                TileType::WATER => 0.0,
+                _ => 0.5 + tile.moisture,
+            }
+        });
+        let mut from_mountains = Grid::from_fn(width, height, |row, col| {
+            tiles[(row, col)].altitude >= RIVER_SOURCE_ALTITUDE
+        });
+
+        for &(row, col) in flood.order.iter().rev() {
+            let Some(downstream) = flood.direction[(row, col)] else {
+                continue;
+            };
+            let (dr, dc) = downstream.offset();
+            let (r, c) = ((row as isize + dr) as usize, (col as isize + dc) as usize);
+            flow[(r, c)] += flow[(row, col)];
+            if from_mountains[(row, col)] {
+                from_mountains[(r, c)] = true;
+            }
+        }
+
+        let river = Grid::from_fn(width, height, |row, col| {
+            tiles[(row, col)].tile_type != TileType::WATER
+                && from_mountains[(row, col)]
+                && flow[(row, col)] >= RIVER_MIN_FLOW
+        });
+        (flow, river)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::seed::WorldSeed;
+    use crate::game::world_map::WorldMap;
+
+    fn tiles_for(altitudes: &Grid<f32>) -> Grid<Tile> {
+        Grid::from_fn(altitudes.width(), altitudes.height(), |row, col| {
+            let altitude = altitudes[(row, col)].clamp(0.0, 255.0) as u8;
+            Tile {
+                tile_type: match altitude {
+                    0 => TileType::WATER,
+                    _ => TileType::GRASS,
+                },
+                altitude,
+                temperature: 0.0,
+                moisture: 0.5,
+                river_flow: 0.0,
+                river_direction: None,
+            }
+        })
+    }
+
+    fn downstream(direction: Direction, row: usize, col: usize) -> (usize, usize) {
+        let (dr, dc) = direction.offset();
+        ((row as isize + dr) as usize, (col as isize + dc) as usize)
+    }
+
+    #[test]
+    fn flooding_leaves_no_interior_pits() {
+        let altitudes = WorldMap::diamond_square(WorldSeed::new(11), 33, 33);
+        let tiles = tiles_for(&altitudes);
+        let hydrology = Hydrology::new(&altitudes, &tiles);
+
+        for row in 0..33 {
+            for col in 0..33 {
+                if tiles[(row, col)].tile_type == TileType::WATER {
+                    continue;
+                }
+                let filled = hydrology.filled[(row, col)];
+                assert!(filled >= altitudes[(row, col)]);
+                // Every land cell spills into a neighbour no higher than itself
+                let direction = hydrology.direction[(row, col)].expect("land cell without outlet");
+                assert!(hydrology.filled[downstream(direction, row, col)] <= filled);
+            }
+        }
+    }
+
+    #[test]
+    fn every_land_tile_drains_downhill_to_the_sea() {
+        let altitudes = WorldMap::diamond_square(WorldSeed::new(5), 33, 33);
+        let tiles = tiles_for(&altitudes);
+        let hydrology = Hydrology::new(&altitudes, &tiles);
+
+        for row in 0..33 {
+            for col in 0..33 {
+                let (mut r, mut c) = (row, col);
+                let mut steps = 0;
+                while tiles[(r, c)].tile_type != TileType::WATER {
+                    let (next_r, next_c) = downstream(hydrology.direction[(r, c)].unwrap(), r, c);
+                    assert!(hydrology.filled[(next_r, next_c)] <= hydrology.filled[(r, c)]);
+                    (r, c) = (next_r, next_c);
+                    steps += 1;
+                    assert!(steps <= 33 * 33, "drainage loop from ({row}, {col})");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn a_basin_fills_up_to_its_rim_as_a_lake() {
+        // Sea and a low coast on the west, a rim at 60 around a basin at 10, highlands behind
+        let altitudes = Grid::from_fn(7, 5, |row, col| match (row, col) {
+            (_, 0) => 0.0,
+            (_, 1) => 30.0,
+            (2, 3) => 10.0,
+            (1..=3, 2..=4) => 60.0,
+            _ => 120.0,
+        });
+        let tiles = tiles_for(&altitudes);
+        let hydrology = Hydrology::new(&altitudes, &tiles);
+
+        assert_eq!(hydrology.filled[(2, 3)], 60.0);
+        assert!(hydrology.is_lake(&altitudes, 2, 3));
+        assert!(!hydrology.is_lake(&altitudes, 2, 2));
+    }
+}
@@ -1,4 +1,15 @@
 pub mod character;
+pub mod chunk_manager;
 pub mod chunk_map;
+pub mod climate;
+pub mod erosion;
+#[allow(clippy::module_inception)]
 pub mod game;
+pub mod grid;
+pub mod hydrology;
+pub mod noise;
+pub mod pathfinding;
+pub mod save;
+pub mod seed;
+pub mod timestep;
 pub mod world_map;
@@ -0,0 +1,348 @@
+use crate::game::seed::WorldSeed;
+
+/// A 2D noise field. Every source returns values roughly in `-1.0..=1.0` and every
+/// combinator keeps that range, so they can be nested freely:
+///
+/// ```
+/// use airomad2::game::noise::{DomainWarp, Fractal, Noise, Perlin, Simplex};
+/// use airomad2::game::seed::WorldSeed;
+///
+/// let seed = WorldSeed::new(1);
+/// let terrain = DomainWarp::new(
+///     Fractal::ridged(Perlin::new(seed), 5).frequency(0.03),
+///     Fractal::fbm(Simplex::new(seed.noise_seed()), 3).frequency(0.01),
+///     8.0,
+/// );
+/// let height = terrain.get(12.5, -3.0);
+/// assert!((-1.0..=1.0).contains(&height));
+/// ```
+pub trait Noise {
+    fn get(&self, x: f32, y: f32) -> f32;
+}
+
+/// Classic gradient noise on a square lattice.
+pub struct Perlin {
+    seed: WorldSeed,
+}
+
+/// Gradient noise on a triangular lattice, fewer directional artifacts than Perlin.
+pub struct Simplex {
+    seed: WorldSeed,
+}
+
+/// Smoothly interpolated random values on a square lattice, blockier than gradient noise.
+pub struct Value {
+    seed: WorldSeed,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum FractalKind {
+    /// Plain sum of octaves, rolling terrain.
+    FBM,
+    /// Sharp crests where the source crosses zero, each octave weighted by the previous one.
+    RIDGED,
+    /// Rounded bumps, the absolute value of every octave.
+    BILLOW,
+}
+
+/// Sums `octaves` layers of a source, each `lacunarity` times finer and `gain` times weaker
+/// than the previous one. The result is normalized back to the source range.
+pub struct Fractal<N: Noise> {
+    pub source: N,
+    pub kind: FractalKind,
+    pub octaves: usize,
+    pub frequency: f32,
+    pub lacunarity: f32,
+    pub gain: f32,
+}
+
+/// Offsets the sampling position of `source` by `warp`, `strength` being the largest offset
+/// in input units.
+pub struct DomainWarp<N: Noise, W: Noise> {
+    pub source: N,
+    pub warp: W,
+    pub strength: f32,
+}
+
+impl Perlin {
+    pub fn new(seed: WorldSeed) -> Self {
+        Self { seed }
+    }
+}
+
+impl Noise for Perlin {
+    fn get(&self, x: f32, y: f32) -> f32 {
+        let (cell_col, cell_row) = (x.floor(), y.floor());
+        let (offset_x, offset_y) = (x - cell_col, y - cell_row);
+        let (cell_col, cell_row) = (cell_col as i32, cell_row as i32);
+
+        let corner = |row: i32, col: i32, offset: (f32, f32)| {
+            let gradient = gradient(self.seed, row, col);
+            gradient.0 * offset.0 + gradient.1 * offset.1
+        };
+        let dot_product_tl = corner(cell_row, cell_col, (offset_x, offset_y));
+        let dot_product_tr = corner(cell_row, cell_col + 1, (offset_x - 1.0, offset_y));
+        let dot_product_bl = corner(cell_row + 1, cell_col, (offset_x, offset_y - 1.0));
+        let dot_product_br = corner(cell_row + 1, cell_col + 1, (offset_x - 1.0, offset_y - 1.0));
+
+        let height = lerp(
+            lerp(dot_product_tl, dot_product_bl, fade(offset_y)),
+            lerp(dot_product_tr, dot_product_br, fade(offset_y)),
+            fade(offset_x),
+        );
+        // Unit gradients peak at sqrt(0.5)
+        height * std::f32::consts::SQRT_2
+    }
+}
+
+impl Simplex {
+    pub fn new(seed: WorldSeed) -> Self {
+        Self { seed }
+    }
+}
+
+impl Noise for Simplex {
+    fn get(&self, x: f32, y: f32) -> f32 {
+        let skew = 0.5 * (f32::sqrt(3.0) - 1.0);
+        let unskew = (3.0 - f32::sqrt(3.0)) / 6.0;
+
+        // Find the triangle containing the point in the skewed lattice
+        let s = (x + y) * skew;
+        let (col, row) = ((x + s).floor(), (y + s).floor());
+        let t = (col + row) * unskew;
+        let (x0, y0) = (x - (col - t), y - (row - t));
+        let (col, row) = (col as i32, row as i32);
+        let (step_col, step_row) = if x0 > y0 { (1, 0) } else { (0, 1) };
+
+        let corners = [
+            (row, col, x0, y0),
+            (
+                row + step_row,
+                col + step_col,
+                x0 - step_col as f32 + unskew,
+                y0 - step_row as f32 + unskew,
+            ),
+            (
+                row + 1,
+                col + 1,
+                x0 - 1.0 + 2.0 * unskew,
+                y0 - 1.0 + 2.0 * unskew,
+            ),
+        ];
+
+        let mut sum = 0.0;
+        for (corner_row, corner_col, dx, dy) in corners {
+            let falloff = 0.5 - dx * dx - dy * dy;
+            if falloff > 0.0 {
+                let gradient = gradient(self.seed, corner_row, corner_col);
+                sum += falloff.powi(4) * (gradient.0 * dx + gradient.1 * dy);
+            }
+        }
+        // The usual 70 assumes gradients of length sqrt(2), these are unit vectors
+        (70.0 * std::f32::consts::SQRT_2 * sum).clamp(-1.0, 1.0)
+    }
+}
+
+impl Value {
+    pub fn new(seed: WorldSeed) -> Self {
+        Self { seed }
+    }
+}
+
+impl Noise for Value {
+    fn get(&self, x: f32, y: f32) -> f32 {
+        let (cell_col, cell_row) = (x.floor(), y.floor());
+        let (offset_x, offset_y) = (fade(x - cell_col), fade(y - cell_row));
+        let (cell_col, cell_row) = (cell_col as i32, cell_row as i32);
+
+        let value = |row: i32, col: i32| unit(self.seed, row, col) * 2.0 - 1.0;
+        lerp(
+            lerp(
+                value(cell_row, cell_col),
+                value(cell_row + 1, cell_col),
+                offset_y,
+            ),
+            lerp(
+                value(cell_row, cell_col + 1),
+                value(cell_row + 1, cell_col + 1),
+                offset_y,
+            ),
+            offset_x,
+        )
+    }
+}
+
+impl<N: Noise> Fractal<N> {
+    pub fn new(source: N, kind: FractalKind, octaves: usize) -> Self {
+        Self {
+            source,
+            kind,
+            octaves,
+            frequency: 1.0,
+            lacunarity: 2.0,
+            gain: 0.5,
+        }
+    }
+
+    pub fn fbm(source: N, octaves: usize) -> Self {
+        Self::new(source, FractalKind::FBM, octaves)
+    }
+
+    pub fn ridged(source: N, octaves: usize) -> Self {
+        Self::new(source, FractalKind::RIDGED, octaves)
+    }
+
+    pub fn billow(source: N, octaves: usize) -> Self {
+        Self::new(source, FractalKind::BILLOW, octaves)
+    }
+
+    pub fn frequency(mut self, frequency: f32) -> Self {
+        self.frequency = frequency;
+        self
+    }
+
+    pub fn lacunarity(mut self, lacunarity: f32) -> Self {
+        self.lacunarity = lacunarity;
+        self
+    }
+
+    pub fn gain(mut self, gain: f32) -> Self {
+        self.gain = gain;
+        self
+    }
+}
+
+impl<N: Noise> Noise for Fractal<N> {
+    fn get(&self, x: f32, y: f32) -> f32 {
+        let mut frequency = self.frequency;
+        let mut amplitude = 1.0;
+        let mut total_amplitude = 0.0;
+        let mut weight = 1.0;
+        let mut sum = 0.0;
+
+        for octave in 0..self.octaves {
+            // Shift every octave so their lattices don't line up at the origin
+            let shift = octave as f32 * 17.31;
+            let sample = self
+                .source
+                .get(x * frequency + shift, y * frequency - shift);
+
+            let signal = match self.kind {
+                FractalKind::FBM => sample,
+                FractalKind::BILLOW => sample.abs() * 2.0 - 1.0,
+                FractalKind::RIDGED => {
+                    let ridge = (1.0 - sample.abs()).powi(2) * weight;
+                    weight = (ridge * 2.0).clamp(0.0, 1.0);
+                    ridge * 2.0 - 1.0
+                }
+            };
+
+            sum += signal * amplitude;
+            total_amplitude += amplitude;
+            frequency *= self.lacunarity;
+            amplitude *= self.gain;
+        }
+
+        if total_amplitude > 0.0 {
+            sum / total_amplitude
+        } else {
+            0.0
+        }
+    }
+}
+
+impl<N: Noise, W: Noise> DomainWarp<N, W> {
+    pub fn new(source: N, warp: W, strength: f32) -> Self {
+        Self {
+            source,
+            warp,
+            strength,
+        }
+    }
+}
+
+impl<N: Noise, W: Noise> Noise for DomainWarp<N, W> {
+    fn get(&self, x: f32, y: f32) -> f32 {
+        // Sampling the warp a second time far away gives an independent offset for y
+        let warp_x = self.warp.get(x, y);
+        let warp_y = self.warp.get(x + 131.7, y - 91.3);
+        self.source
+            .get(x + warp_x * self.strength, y + warp_y * self.strength)
+    }
+}
+
+// Random unit vector of a lattice point
+fn gradient(seed: WorldSeed, row: i32, col: i32) -> (f32, f32) {
+    let theta = unit(seed, row, col) * 2.0 * std::f32::consts::PI;
+    (theta.cos(), theta.sin())
+}
+
+// Random value in 0.0..1.0 of a lattice point
+fn unit(seed: WorldSeed, row: i32, col: i32) -> f32 {
+    (seed.cell_seed(row, col).0 >> 40) as f32 / (1u64 << 24) as f32
+}
+
+fn lerp(a0: f32, a1: f32, w: f32) -> f32 {
+    (1.0 - w) * a0 + w * a1
+}
+
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples() -> impl Iterator<Item = (f32, f32)> {
+        (0..40).flat_map(|i| (0..40).map(move |j| (i as f32 * 0.37 - 7.0, j as f32 * 0.53 - 9.0)))
+    }
+
+    fn check_range(noise: &impl Noise) {
+        for (x, y) in samples() {
+            let value = noise.get(x, y);
+            assert!((-1.0..=1.0).contains(&value), "{value} at ({x}, {y})");
+        }
+    }
+
+    #[test]
+    fn every_source_and_combinator_stays_in_range() {
+        let seed = WorldSeed::new(21);
+        check_range(&Perlin::new(seed));
+        check_range(&Simplex::new(seed));
+        check_range(&Value::new(seed));
+        check_range(&Fractal::fbm(Perlin::new(seed), 5).frequency(0.3));
+        check_range(&Fractal::ridged(Simplex::new(seed), 5).gain(0.7));
+        check_range(&Fractal::billow(Value::new(seed), 4).lacunarity(3.0));
+        check_range(&DomainWarp::new(
+            Fractal::fbm(Perlin::new(seed), 3),
+            Simplex::new(seed.noise_seed()),
+            4.0,
+        ));
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_field() {
+        let noise = |seed| Fractal::fbm(Simplex::new(WorldSeed::new(seed)), 4).frequency(0.2);
+        let (a, again, other) = (noise(5), noise(5), noise(6));
+        assert!(samples().all(|(x, y)| a.get(x, y) == again.get(x, y)));
+        assert!(samples().any(|(x, y)| a.get(x, y) != other.get(x, y)));
+    }
+
+    #[test]
+    fn fields_are_continuous_across_lattice_lines() {
+        // Chunk borders fall on integer coordinates, so do the lattice cells
+        let seed = WorldSeed::new(8);
+        let sources: [&dyn Noise; 3] = [&Perlin::new(seed), &Simplex::new(seed), &Value::new(seed)];
+        for noise in sources {
+            for i in -5..5 {
+                for j in -5..5 {
+                    let (x, y) = (i as f32, j as f32 + 0.3);
+                    let step = 1e-3;
+                    assert!((noise.get(x - step, y) - noise.get(x + step, y)).abs() < 0.05);
+                    assert!((noise.get(y, x - step) - noise.get(y, x + step)).abs() < 0.05);
+                }
+            }
+        }
+    }
+}
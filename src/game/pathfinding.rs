@@ -0,0 +1,524 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use crate::constants::{CHARACTER_MAX_SLOPE, CHARACTER_WADING_SPEED, PATH_CLIMB_COST};
+use crate::game::chunk_map::{ChunkMap, ChunkTileType};
+use crate::game::grid::{Direction, Grid};
+use crate::game::world_map::{TileType, WorldMap};
+
+/// A route found by `find_path`. Waypoints are `[x, y]` positions in cells of the searched
+/// grid, after string-pulling, and the cost is the one of walking the straight segments
+/// between them.
+pub struct Path {
+    pub waypoints: Vec<[f32; 2]>,
+    pub cost: f32,
+}
+
+/// Terrain the A* search runs over.
+pub trait PathGrid {
+    fn width(&self) -> usize;
+    fn height(&self) -> usize;
+    /// Cost per cell width of moving from `from` to its neighbour `to`, `None` if it's blocked.
+    fn step_cost(&self, from: (usize, usize), to: (usize, usize)) -> Option<f32>;
+    /// Lowest cost per cell width anywhere in the grid, keeps the heuristic admissible.
+    fn min_step_cost(&self) -> f32;
+}
+
+/// Walking over the tiles of a chunk: tile types make walking slower or impossible and
+/// climbs get more expensive with their slope, up to the same limit as the character.
+pub struct ChunkTerrain<'a> {
+    chunk: &'a ChunkMap,
+    heights: Grid<f32>,
+}
+
+/// Travelling over the world map, on foot or by boat.
+pub struct WorldTerrain<'a> {
+    world: &'a WorldMap,
+    by_boat: bool,
+}
+
+struct PathCell {
+    estimate: f32,
+    order: usize,
+    row: usize,
+    col: usize,
+}
+
+// Reversed so that BinaryHeap pops the lowest estimate first, ties in insertion order
+impl Ord for PathCell {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .estimate
+            .total_cmp(&self.estimate)
+            .then(other.order.cmp(&self.order))
+    }
+}
+
+impl PartialOrd for PathCell {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for PathCell {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for PathCell {}
+
+impl<'a> ChunkTerrain<'a> {
+    pub fn new(chunk: &'a ChunkMap) -> Self {
+        let heights = Grid::from_fn(
+            chunk.tile_types.width(),
+            chunk.tile_types.height(),
+            |row, col| chunk.height_at(col as f32 + 0.5, row as f32 + 0.5),
+        );
+        Self { chunk, heights }
+    }
+
+    fn tile_cost(tile_type: ChunkTileType) -> Option<f32> {
+        match tile_type {
+            ChunkTileType::DEEP_WATER => None,
+            ChunkTileType::SHALLOW_WATER => Some(1.0 / CHARACTER_WADING_SPEED),
+            ChunkTileType::DIRT | ChunkTileType::GRASS => Some(1.0),
+            ChunkTileType::SAND => Some(1.2),
+            ChunkTileType::FOREST_FLOOR => Some(1.3),
+            ChunkTileType::SNOW => Some(1.8),
+            ChunkTileType::ROCK => Some(2.0),
+        }
+    }
+}
+
+impl PathGrid for ChunkTerrain<'_> {
+    fn width(&self) -> usize {
+        self.heights.width()
+    }
+
+    fn height(&self) -> usize {
+        self.heights.height()
+    }
+
+    fn step_cost(&self, from: (usize, usize), to: (usize, usize)) -> Option<f32> {
+        let cost = Self::tile_cost(self.chunk.tile_types[to])?;
+        let climb = (self.heights[to] - self.heights[from]).max(0.0) / distance(from, to);
+        if climb > CHARACTER_MAX_SLOPE.to_radians().tan() {
+            return None;
+        }
+        Some(cost * (1.0 + PATH_CLIMB_COST * climb))
+    }
+
+    fn min_step_cost(&self) -> f32 {
+        1.0
+    }
+}
+
+impl<'a> WorldTerrain<'a> {
+    pub fn new(world: &'a WorldMap, by_boat: bool) -> Self {
+        Self { world, by_boat }
+    }
+
+    fn tile_cost(&self, tile_type: TileType) -> Option<f32> {
+        if self.by_boat {
+            return match tile_type {
+                TileType::WATER | TileType::LAKE => Some(1.0),
+                _ => None,
+            };
+        }
+        match tile_type {
+            TileType::WATER | TileType::LAKE => None,
+            TileType::GRASS | TileType::STEPPE => Some(1.0),
+            TileType::WOODS | TileType::TUNDRA => Some(1.5),
+            TileType::DESERT | TileType::HILLS => Some(2.0),
+            TileType::JUNGLE => Some(2.5),
+            TileType::SWAMP | TileType::HIGHLANDS => Some(3.0),
+            TileType::SNOW => Some(4.0),
+            TileType::MOUNTAINS => Some(6.0),
+        }
+    }
+}
+
+impl PathGrid for WorldTerrain<'_> {
+    fn width(&self) -> usize {
+        self.world.tiles.width()
+    }
+
+    fn height(&self) -> usize {
+        self.world.tiles.height()
+    }
+
+    fn step_cost(&self, _from: (usize, usize), to: (usize, usize)) -> Option<f32> {
+        self.tile_cost(self.world.tiles[to].tile_type)
+    }
+
+    fn min_step_cost(&self) -> f32 {
+        1.0
+    }
+}
+
+/// Cheapest route between two cells with A* over the 8 neighbours, then string-pulled into
+/// straight segments wherever the terrain in between is walkable and no more expensive than
+/// the cells they replace. Endpoints outside the grid have no path.
+pub fn find_path(
+    grid: &impl PathGrid,
+    start: (usize, usize),
+    goal: (usize, usize),
+) -> Option<Path> {
+    let inside = |(row, col): (usize, usize)| row < grid.height() && col < grid.width();
+    if !inside(start) || !inside(goal) {
+        return None;
+    }
+    let (cells, costs) = a_star(grid, start, goal)?;
+    let (waypoints, cost) = string_pull(grid, &cells, &costs);
+    let waypoints = waypoints
+        .into_iter()
+        .map(|(row, col)| [col as f32 + 0.5, row as f32 + 0.5])
+        .collect();
+    Some(Path { waypoints, cost })
+}
+
+/// Route between two positions inside a chunk, given in chunk tiles from its (0, 0) edge.
+pub fn chunk_path(chunk: &ChunkMap, start: [f32; 2], goal: [f32; 2]) -> Option<Path> {
+    let terrain = ChunkTerrain::new(chunk);
+    let cell = |position: [f32; 2]| {
+        (
+            (position[1].max(0.0) as usize).min(terrain.height() - 1),
+            (position[0].max(0.0) as usize).min(terrain.width() - 1),
+        )
+    };
+    let mut path = find_path(&terrain, cell(start), cell(goal))?;
+
+    // Start and end exactly where asked instead of at the tile centers
+    if let Some(first) = path.waypoints.first_mut() {
+        *first = start;
+    }
+    if let Some(last) = path.waypoints.last_mut() {
+        *last = goal;
+    }
+    Some(path)
+}
+
+/// Route between two world tiles, `by_boat` sails over water instead of walking over land.
+pub fn world_path(
+    world: &WorldMap,
+    start: (usize, usize),
+    goal: (usize, usize),
+    by_boat: bool,
+) -> Option<Path> {
+    find_path(&WorldTerrain::new(world, by_boat), start, goal)
+}
+
+// Cells of a route and the cost of reaching each of them from its start
+type Route = (Vec<(usize, usize)>, Vec<f32>);
+
+fn a_star(grid: &impl PathGrid, start: (usize, usize), goal: (usize, usize)) -> Option<Route> {
+    let (width, height) = (grid.width(), grid.height());
+    let mut cost = Grid::new(width, height, f32::INFINITY);
+    let mut came_from: Grid<Option<(usize, usize)>> = Grid::new(width, height, None);
+    let mut closed = Grid::new(width, height, false);
+    let mut queue = BinaryHeap::new();
+    let heuristic = |cell: (usize, usize)| octile_distance(cell, goal) * grid.min_step_cost();
+
+    cost[start] = 0.0;
+    queue.push(PathCell {
+        estimate: heuristic(start),
+        order: 0,
+        row: start.0,
+        col: start.1,
+    });
+    let mut order = 1;
+
+    while let Some(PathCell { row, col, .. }) = queue.pop() {
+        let current = (row, col);
+        if current == goal {
+            let mut cells = vec![goal];
+            while let Some(previous) = came_from[*cells.last().unwrap()] {
+                cells.push(previous);
+            }
+            cells.reverse();
+            let costs = cells.iter().map(|&cell| cost[cell]).collect();
+            return Some((cells, costs));
+        }
+        if closed[current] {
+            continue;
+        }
+        closed[current] = true;
+
+        for direction in Direction::ALL {
+            let Some(next) = neighbour(grid, current, direction) else {
+                continue;
+            };
+            if closed[next] {
+                continue;
+            }
+            // No cutting corners: a diagonal step needs both sides to be open
+            let (dr, dc) = direction.offset();
+            if dr != 0
+                && dc != 0
+                && (grid.step_cost(current, (next.0, col)).is_none()
+                    || grid.step_cost(current, (row, next.1)).is_none())
+            {
+                continue;
+            }
+            let Some(step_cost) = grid.step_cost(current, next) else {
+                continue;
+            };
+
+            let next_cost = cost[current] + step_cost * distance(current, next);
+            if next_cost < cost[next] {
+                cost[next] = next_cost;
+                came_from[next] = Some(current);
+                queue.push(PathCell {
+                    estimate: next_cost + heuristic(next),
+                    order,
+                    row: next.0,
+                    col: next.1,
+                });
+                order += 1;
+            }
+        }
+    }
+    None
+}
+
+// Keeps only the cells where the path has to turn: from each kept cell, skips ahead to the
+// farthest cell that can be reached in a straight line for no more than the A* route costs
+// up to there. Returns the kept cells and the cost of the straight segments between them
+fn string_pull(
+    grid: &impl PathGrid,
+    cells: &[(usize, usize)],
+    costs: &[f32],
+) -> (Vec<(usize, usize)>, f32) {
+    let Some(&first) = cells.first() else {
+        return (Vec::new(), 0.0);
+    };
+    let mut waypoints = vec![first];
+    let mut total = 0.0;
+    let mut anchor = 0;
+    // Cost of the segment from the anchor to the previous cell, the last accepted one
+    let mut accepted = 0.0;
+
+    for index in 1..cells.len() {
+        let route_cost = costs[index] - costs[anchor];
+        // A single step is the one A* took, corners included
+        let shortcut = match index - anchor {
+            1 => Some(route_cost),
+            _ => segment_cost(grid, cells[anchor], cells[index])
+                .filter(|cost| *cost <= route_cost + 1e-4),
+        };
+        match shortcut {
+            Some(cost) => accepted = cost,
+            None => {
+                anchor = index - 1;
+                waypoints.push(cells[anchor]);
+                total += accepted;
+                accepted = costs[index] - costs[anchor];
+            }
+        }
+    }
+    if cells.len() > 1 {
+        waypoints.push(cells[cells.len() - 1]);
+        total += accepted;
+    }
+    (waypoints, total)
+}
+
+// Walks the segment between two cell centers in small steps, every cell it enters has to be
+// enterable from the previous one. Each cell entered costs its step cost times an equal share
+// of the segment length, `None` if the segment is blocked
+fn segment_cost(grid: &impl PathGrid, from: (usize, usize), to: (usize, usize)) -> Option<f32> {
+    let (from_x, from_y) = (from.1 as f32 + 0.5, from.0 as f32 + 0.5);
+    let (to_x, to_y) = (to.1 as f32 + 0.5, to.0 as f32 + 0.5);
+    let steps = (distance(from, to) * 4.0).ceil() as usize;
+
+    let mut previous = from;
+    let mut step_costs = Vec::new();
+    for step in 1..=steps {
+        let t = step as f32 / steps as f32;
+        let x = from_x + (to_x - from_x) * t;
+        let y = from_y + (to_y - from_y) * t;
+        let cell = (y as usize, x as usize);
+        if cell == previous {
+            continue;
+        }
+        // Crossing a corner diagonally has to be possible through both sides
+        if cell.0 != previous.0
+            && cell.1 != previous.1
+            && (grid.step_cost(previous, (cell.0, previous.1)).is_none()
+                || grid.step_cost(previous, (previous.0, cell.1)).is_none())
+        {
+            return None;
+        }
+        step_costs.push(grid.step_cost(previous, cell)?);
+        previous = cell;
+    }
+    if step_costs.is_empty() {
+        return Some(0.0);
+    }
+    let share = distance(from, to) / step_costs.len() as f32;
+    Some(step_costs.iter().map(|cost| cost * share).sum())
+}
+
+fn neighbour(
+    grid: &impl PathGrid,
+    cell: (usize, usize),
+    direction: Direction,
+) -> Option<(usize, usize)> {
+    let (dr, dc) = direction.offset();
+    let (row, col) = (cell.0 as isize + dr, cell.1 as isize + dc);
+    if row < 0 || col < 0 || row >= grid.height() as isize || col >= grid.width() as isize {
+        return None;
+    }
+    Some((row as usize, col as usize))
+}
+
+fn distance(from: (usize, usize), to: (usize, usize)) -> f32 {
+    let (rows, cols) = (from.0.abs_diff(to.0) as f32, from.1.abs_diff(to.1) as f32);
+    f32::sqrt(rows * rows + cols * cols)
+}
+
+// Exact distance on an 8-connected grid without obstacles
+fn octile_distance(from: (usize, usize), to: (usize, usize)) -> f32 {
+    let (rows, cols) = (from.0.abs_diff(to.0) as f32, from.1.abs_diff(to.1) as f32);
+    rows.max(cols) + (std::f32::consts::SQRT_2 - 1.0) * rows.min(cols)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::seed::WorldSeed;
+
+    // '.' is open ground, 'm' marsh five times as slow, '~' water that can't be walked
+    struct Terrain(Grid<char>);
+
+    impl Terrain {
+        fn new(rows: &[&str]) -> Self {
+            let rows: Vec<Vec<char>> = rows.iter().map(|row| row.chars().collect()).collect();
+            Self(Grid::from_fn(rows[0].len(), rows.len(), |row, col| {
+                rows[row][col]
+            }))
+        }
+
+        // Cells crossed by the straight segment between two cell centers, sampled between
+        // lattice points so that grazing a corner doesn't count
+        fn crossed(&self, from: (usize, usize), to: (usize, usize)) -> Vec<char> {
+            let steps = (distance(from, to) * 8.0).ceil() as usize;
+            (0..steps)
+                .map(|step| {
+                    let t = (step as f32 + 0.5) / steps as f32;
+                    let x = from.1 as f32 + 0.5 + (to.1 as f32 - from.1 as f32) * t;
+                    let y = from.0 as f32 + 0.5 + (to.0 as f32 - from.0 as f32) * t;
+                    self.0[(y as usize, x as usize)]
+                })
+                .collect()
+        }
+    }
+
+    impl PathGrid for Terrain {
+        fn width(&self) -> usize {
+            self.0.width()
+        }
+
+        fn height(&self) -> usize {
+            self.0.height()
+        }
+
+        fn step_cost(&self, _from: (usize, usize), to: (usize, usize)) -> Option<f32> {
+            match self.0[to] {
+                '~' => None,
+                'm' => Some(5.0),
+                _ => Some(1.0),
+            }
+        }
+
+        fn min_step_cost(&self) -> f32 {
+            1.0
+        }
+    }
+
+    fn cell(waypoint: [f32; 2]) -> (usize, usize) {
+        (waypoint[1] as usize, waypoint[0] as usize)
+    }
+
+    #[test]
+    fn blocked_or_enclosed_goal_has_no_path() {
+        let terrain = Terrain::new(&["......", "...~~~", "...~.~", "...~~~"]);
+        assert!(find_path(&terrain, (0, 0), (1, 3)).is_none());
+        assert!(find_path(&terrain, (0, 0), (2, 4)).is_none());
+        assert!(find_path(&terrain, (0, 0), (3, 0)).is_some());
+    }
+
+    #[test]
+    fn endpoints_outside_the_grid_have_no_path() {
+        let terrain = Terrain::new(&["....", "...."]);
+        assert!(find_path(&terrain, (0, 0), (2, 0)).is_none());
+        assert!(find_path(&terrain, (0, 4), (1, 1)).is_none());
+
+        let world = WorldMap::new(WorldSeed::new(2), 5, 5);
+        assert!(world_path(&world, (0, 0), (5, 5), true).is_none());
+        assert!(world_path(&world, (usize::MAX, 0), (1, 1), true).is_none());
+        assert!(world_path(&world, (0, 0), (4, 4), true).is_some());
+    }
+
+    #[test]
+    fn path_detours_around_water() {
+        let terrain = Terrain::new(&[
+            "........", "...~....", "...~....", "...~....", "...~....", "...~....",
+        ]);
+        let path = find_path(&terrain, (4, 0), (4, 7)).unwrap();
+
+        let waypoints: Vec<_> = path.waypoints.iter().map(|&w| cell(w)).collect();
+        assert_eq!(waypoints.first(), Some(&(4, 0)));
+        assert_eq!(waypoints.last(), Some(&(4, 7)));
+        assert!(waypoints.iter().any(|&(row, _)| row == 0));
+        for segment in waypoints.windows(2) {
+            assert!(!terrain.crossed(segment[0], segment[1]).contains(&'~'));
+        }
+        assert!(path.cost > 7.0);
+    }
+
+    #[test]
+    fn diagonal_steps_do_not_cut_corners() {
+        let terrain = Terrain::new(&[".~.", "~..", "..."]);
+        assert!(find_path(&terrain, (0, 0), (2, 2)).is_none());
+
+        // The diagonal gap in the wall is closed, the path has to go round through the bottom
+        let terrain = Terrain::new(&["..~..", "..~..", ".~...", "....."]);
+        let path = find_path(&terrain, (0, 0), (0, 4)).unwrap();
+        let waypoints: Vec<_> = path.waypoints.iter().map(|&w| cell(w)).collect();
+        for segment in waypoints.windows(2) {
+            assert!(segment_cost(&terrain, segment[0], segment[1]).is_some());
+        }
+        assert!(waypoints.iter().any(|&(row, _)| row >= 3));
+    }
+
+    #[test]
+    fn smoothing_never_cuts_through_expensive_terrain() {
+        let terrain = Terrain::new(&["..........", "..mmmmmm..", "..mmmmmm..", "..mmmmmm.."]);
+        let (_, costs) = a_star(&terrain, (2, 0), (2, 9)).unwrap();
+        let path = find_path(&terrain, (2, 0), (2, 9)).unwrap();
+
+        let waypoints: Vec<_> = path.waypoints.iter().map(|&w| cell(w)).collect();
+        for segment in waypoints.windows(2) {
+            assert!(!terrain.crossed(segment[0], segment[1]).contains(&'m'));
+        }
+        assert!(path.cost <= costs.last().unwrap() + 1e-4);
+        assert!(waypoints.len() < 10);
+    }
+
+    #[test]
+    fn smoothing_never_raises_the_cost_on_real_terrain() {
+        let seed = WorldSeed::new(2);
+        let world = WorldMap::new(seed, 33, 33);
+        let chunk = ChunkMap::for_world_tile(&world, 16, 16, seed);
+        let terrain = ChunkTerrain::new(&chunk);
+
+        for (start, goal) in [((5, 5), (90, 80)), ((50, 2), (3, 97)), ((95, 95), (10, 40))] {
+            let (cells, costs) = a_star(&terrain, start, goal).unwrap();
+            let (waypoints, cost) = string_pull(&terrain, &cells, &costs);
+            assert!(cost <= costs.last().unwrap() + 1e-3);
+            assert!(waypoints.len() < cells.len());
+        }
+    }
+}
@@ -0,0 +1,590 @@
+use std::collections::HashMap;
+use std::io;
+use std::sync::Arc;
+
+use crate::constants::{CHUNK_MAP_COLS, CHUNK_MAP_ROWS};
+use crate::game::chunk_manager::ChunkCoord;
+use crate::game::chunk_map::{ChunkMap, ChunkTileType};
+use crate::game::grid::{Direction, Grid};
+use crate::game::seed::WorldSeed;
+use crate::game::world_map::{Tile, TileType, WorldMap};
+
+/// Version written by this build. Bump it when the layout of an existing section changes
+/// and add the step upgrading the previous version to `MIGRATIONS`. New sections, like the
+/// state of future entities, don't need a bump: older builds skip the tags they don't know.
+pub const SAVE_VERSION: u32 = 1;
+
+const SAVE_MAGIC: &[u8; 8] = b"AIROMAD2";
+
+const SEED_SECTION: [u8; 4] = *b"SEED";
+const WORLD_SECTION: [u8; 4] = *b"WRLD";
+const CHUNKS_SECTION: [u8; 4] = *b"CHNK";
+const CHARACTER_SECTION: [u8; 4] = *b"CHAR";
+
+type Sections = HashMap<[u8; 4], Vec<u8>>;
+type Migration = fn(&mut Sections) -> io::Result<()>;
+
+// `MIGRATIONS[v - 1]` rewrites the sections of a version `v` save into version `v + 1`
+const MIGRATIONS: [Migration; SAVE_VERSION as usize - 1] = [];
+
+// The id of a type in a save is its position in these tables, only ever append to them
+const TILE_TYPES: [TileType; 13] = [
+    TileType::WATER,
+    TileType::GRASS,
+    TileType::WOODS,
+    TileType::HILLS,
+    TileType::MOUNTAINS,
+    TileType::HIGHLANDS,
+    TileType::DESERT,
+    TileType::STEPPE,
+    TileType::TUNDRA,
+    TileType::SWAMP,
+    TileType::JUNGLE,
+    TileType::SNOW,
+    TileType::LAKE,
+];
+
+const CHUNK_TILE_TYPES: [ChunkTileType; 8] = [
+    ChunkTileType::DEEP_WATER,
+    ChunkTileType::SHALLOW_WATER,
+    ChunkTileType::SAND,
+    ChunkTileType::DIRT,
+    ChunkTileType::GRASS,
+    ChunkTileType::FOREST_FLOOR,
+    ChunkTileType::ROCK,
+    ChunkTileType::SNOW,
+];
+
+const NO_RIVER: u8 = u8::MAX;
+
+/// Everything a save holds, whatever version it was read from.
+pub struct SaveData {
+    pub seed: WorldSeed,
+    pub tick: u64,
+    pub world_map: Arc<WorldMap>,
+    pub chunks: Vec<(ChunkCoord, Arc<ChunkMap>)>,
+    pub character_position: [f32; 3],
+}
+
+struct SaveWriter {
+    bytes: Vec<u8>,
+}
+
+struct SaveReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+/// Encodes a save: the magic, the version, then tagged sections of `tag, length, data`.
+/// Numbers are little endian.
+pub fn encode(data: &SaveData) -> Vec<u8> {
+    let mut save = SaveWriter::new();
+    save.bytes.extend_from_slice(SAVE_MAGIC);
+    save.u32(SAVE_VERSION);
+
+    let mut seed = SaveWriter::new();
+    seed.u64(data.seed.0);
+    seed.u64(data.tick);
+    save.section(SEED_SECTION, seed);
+
+    let mut world = SaveWriter::new();
+    world.grid(&data.world_map.tiles, SaveWriter::tile);
+    save.section(WORLD_SECTION, world);
+
+    // Sorted so that the same state always gives the same bytes
+    let mut chunks: Vec<_> = data.chunks.iter().collect();
+    chunks.sort_by_key(|(coord, _)| (coord.row, coord.col));
+    let mut chunk_section = SaveWriter::new();
+    chunk_section.u32(chunks.len() as u32);
+    for (coord, chunk) in chunks {
+        chunk_section.i32(coord.row);
+        chunk_section.i32(coord.col);
+        chunk_section.grid(&chunk.tile_types, |writer, tile_type| {
+            writer.u8(save_id(&CHUNK_TILE_TYPES, tile_type))
+        });
+        chunk_section.grid(&chunk.edges, |writer, edge| writer.f32(*edge));
+        chunk_section.grid(&chunk.normals, |writer, normal| {
+            normal.iter().for_each(|value| writer.f32(*value))
+        });
+    }
+    save.section(CHUNKS_SECTION, chunk_section);
+
+    let mut character = SaveWriter::new();
+    data.character_position
+        .iter()
+        .for_each(|value| character.f32(*value));
+    save.section(CHARACTER_SECTION, character);
+
+    save.bytes
+}
+
+/// Decodes a save of this version or an older one, migrating it first.
+pub fn decode(bytes: &[u8]) -> io::Result<SaveData> {
+    decode_with_migrations(bytes, &MIGRATIONS)
+}
+
+// The latest version is the one the last of `migrations` upgrades to
+fn decode_with_migrations(bytes: &[u8], migrations: &[Migration]) -> io::Result<SaveData> {
+    let latest = migrations.len() as u32 + 1;
+    let mut save = SaveReader::new(bytes);
+    if save.bytes(SAVE_MAGIC.len())? != SAVE_MAGIC {
+        return Err(invalid("not a save file"));
+    }
+    let version = save.u32()?;
+    if version == 0 || version > latest {
+        return Err(invalid(format!(
+            "save version {} is not supported, the latest one is {}",
+            version, latest
+        )));
+    }
+
+    let mut sections = Sections::new();
+    while !save.is_empty() {
+        let tag: [u8; 4] = save.bytes(4)?.try_into().unwrap();
+        let length = save.u64()? as usize;
+        sections.insert(tag, save.bytes(length)?.to_vec());
+    }
+    for migration in &migrations[version as usize - 1..] {
+        migration(&mut sections)?;
+    }
+
+    let mut seed = section_reader(&sections, SEED_SECTION)?;
+    let (world_seed, tick) = (WorldSeed::new(seed.u64()?), seed.u64()?);
+
+    let mut world = section_reader(&sections, WORLD_SECTION)?;
+    let tiles = world.grid(SaveReader::tile)?;
+    if tiles.width() == 0 || tiles.height() == 0 {
+        return Err(invalid("the world map is empty"));
+    }
+
+    let mut chunk_section = section_reader(&sections, CHUNKS_SECTION)?;
+    let mut chunks = Vec::new();
+    for _ in 0..chunk_section.u32()? {
+        let coord = ChunkCoord::new(chunk_section.i32()?, chunk_section.i32()?);
+        let tile_types = chunk_section
+            .grid(|reader| from_save_id(&CHUNK_TILE_TYPES, reader.u8()?, "chunk tile type"))?;
+        let edges = chunk_section.grid(SaveReader::f32)?;
+        let normals =
+            chunk_section.grid(|reader| Ok([reader.f32()?, reader.f32()?, reader.f32()?]))?;
+        check_size(
+            &tile_types,
+            CHUNK_MAP_COLS,
+            CHUNK_MAP_ROWS,
+            "chunk tile types",
+        )?;
+        check_size(
+            &edges,
+            CHUNK_MAP_COLS + 1,
+            CHUNK_MAP_ROWS + 1,
+            "chunk edges",
+        )?;
+        check_size(
+            &normals,
+            CHUNK_MAP_COLS + 1,
+            CHUNK_MAP_ROWS + 1,
+            "chunk normals",
+        )?;
+        let chunk = ChunkMap {
+            tile_types,
+            edges,
+            normals,
+        };
+        chunks.push((coord, Arc::new(chunk)));
+    }
+
+    let mut character = section_reader(&sections, CHARACTER_SECTION)?;
+    let character_position = [character.f32()?, character.f32()?, character.f32()?];
+
+    Ok(SaveData {
+        seed: world_seed,
+        tick,
+        world_map: Arc::new(WorldMap { tiles }),
+        chunks,
+        character_position,
+    })
+}
+
+fn section_reader(sections: &Sections, tag: [u8; 4]) -> io::Result<SaveReader<'_>> {
+    sections
+        .get(&tag)
+        .map(|bytes| SaveReader::new(bytes))
+        .ok_or_else(|| invalid(format!("missing section {}", String::from_utf8_lossy(&tag))))
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+fn check_size<T>(grid: &Grid<T>, width: usize, height: usize, name: &str) -> io::Result<()> {
+    if grid.width() != width || grid.height() != height {
+        return Err(invalid(format!(
+            "{} are {}x{} instead of {}x{}",
+            name,
+            grid.width(),
+            grid.height(),
+            width,
+            height
+        )));
+    }
+    Ok(())
+}
+
+fn save_id<T: PartialEq + std::fmt::Debug>(table: &[T], value: &T) -> u8 {
+    table
+        .iter()
+        .position(|entry| entry == value)
+        .unwrap_or_else(|| panic!("{:?} is missing from its save table", value)) as u8
+}
+
+fn from_save_id<T: Copy>(table: &[T], id: u8, name: &str) -> io::Result<T> {
+    table
+        .get(id as usize)
+        .copied()
+        .ok_or_else(|| invalid(format!("unknown {} {}", name, id)))
+}
+
+impl SaveWriter {
+    fn new() -> Self {
+        Self { bytes: Vec::new() }
+    }
+
+    fn section(&mut self, tag: [u8; 4], section: SaveWriter) {
+        self.bytes.extend_from_slice(&tag);
+        self.u64(section.bytes.len() as u64);
+        self.bytes.extend_from_slice(&section.bytes);
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn i32(&mut self, value: i32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn f32(&mut self, value: f32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    // Width and height, then the cells row by row
+    fn grid<T>(&mut self, grid: &Grid<T>, mut cell: impl FnMut(&mut Self, &T)) {
+        self.u32(grid.width() as u32);
+        self.u32(grid.height() as u32);
+        for value in grid.iter() {
+            cell(self, value);
+        }
+    }
+
+    fn tile(&mut self, tile: &Tile) {
+        self.u8(save_id(&TILE_TYPES, &tile.tile_type));
+        self.u8(tile.altitude);
+        self.f32(tile.temperature);
+        self.f32(tile.moisture);
+        self.f32(tile.river_flow);
+        self.u8(tile
+            .river_direction
+            .map_or(NO_RIVER, |direction| save_id(&Direction::ALL, &direction)));
+    }
+}
+
+impl<'a> SaveReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, position: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.position >= self.bytes.len()
+    }
+
+    fn bytes(&mut self, count: usize) -> io::Result<&'a [u8]> {
+        let end = self
+            .position
+            .checked_add(count)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| invalid("save file is truncated"))?;
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        Ok(self.bytes(N)?.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn i32(&mut self) -> io::Result<i32> {
+        Ok(i32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    fn f32(&mut self) -> io::Result<f32> {
+        Ok(f32::from_le_bytes(self.array()?))
+    }
+
+    fn grid<T>(&mut self, mut cell: impl FnMut(&mut Self) -> io::Result<T>) -> io::Result<Grid<T>> {
+        let (width, height) = (self.u32()? as usize, self.u32()? as usize);
+        let mut cells = Vec::with_capacity((width * height).min(self.bytes.len()));
+        for _ in 0..width * height {
+            cells.push(cell(self)?);
+        }
+        let mut cells = cells.into_iter();
+        Ok(Grid::from_fn(width, height, |_, _| cells.next().unwrap()))
+    }
+
+    fn tile(&mut self) -> io::Result<Tile> {
+        let tile_type = from_save_id(&TILE_TYPES, self.u8()?, "tile type")?;
+        let altitude = self.u8()?;
+        let temperature = self.f32()?;
+        let moisture = self.f32()?;
+        let river_flow = self.f32()?;
+        let river_direction = match self.u8()? {
+            NO_RIVER => None,
+            direction => Some(from_save_id(&Direction::ALL, direction, "river direction")?),
+        };
+        Ok(Tile {
+            tile_type,
+            altitude,
+            temperature,
+            moisture,
+            river_flow,
+            river_direction,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::character::CharacterMovement;
+    use crate::game::game::Game;
+
+    #[test]
+    fn save_round_trips() {
+        let mut game = Game::new(WorldSeed::new(7), 9, 9);
+        let mut movement = CharacterMovement::new();
+        movement.moving = true;
+        movement.direction = [0.6, 0.8];
+        for _ in 0..200 {
+            game.update(movement, 0.008);
+        }
+        game.finish_loading();
+
+        let path = std::env::temp_dir().join(format!("airomad2-save-{}.sav", std::process::id()));
+        game.save(&path).unwrap();
+        let loaded = Game::load(&path);
+        let saved_bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+
+        let (exports, loaded_exports) = (game.get_exports(), loaded.get_exports());
+        assert_eq!(exports.tick, loaded_exports.tick);
+        assert_eq!(exports.character, loaded_exports.character);
+        assert_eq!(
+            game.get_for_init().world_map.tiles,
+            loaded.get_for_init().world_map.tiles
+        );
+        let sorted = |mut chunks: Vec<(ChunkCoord, Arc<ChunkMap>)>| {
+            chunks.sort_by_key(|(coord, _)| (coord.row, coord.col));
+            chunks
+        };
+        assert_eq!(sorted(exports.chunks), sorted(loaded_exports.chunks));
+
+        // Saving what was loaded gives the same file back
+        assert_eq!(encode(&loaded.save_data()), saved_bytes);
+    }
+
+    #[test]
+    fn unknown_sections_are_skipped_and_newer_versions_refused() {
+        let mut game = Game::new(WorldSeed::new(7), 9, 9);
+        game.finish_loading();
+        let mut bytes = encode(&game.save_data());
+
+        let mut extra = SaveWriter::new();
+        extra.u32(123);
+        let mut writer = SaveWriter { bytes };
+        writer.section(*b"ENTS", extra);
+        bytes = writer.bytes;
+        let loaded = decode(&bytes).unwrap();
+        assert_eq!(loaded.character_position, game.get_exports().character);
+        assert!(decode(&bytes[..bytes.len() - 1]).is_err());
+
+        bytes[SAVE_MAGIC.len()..SAVE_MAGIC.len() + 4]
+            .copy_from_slice(&(SAVE_VERSION + 1).to_le_bytes());
+        assert!(decode(&bytes).is_err());
+    }
+
+    fn tile(tile_type: TileType, river_direction: Option<Direction>) -> Tile {
+        Tile {
+            tile_type,
+            altitude: 12,
+            temperature: 0.5,
+            moisture: 0.25,
+            river_flow: 3.0,
+            river_direction,
+        }
+    }
+
+    fn chunk(tile_type: impl Fn(usize) -> ChunkTileType, edges: usize) -> ChunkMap {
+        ChunkMap {
+            tile_types: Grid::from_fn(CHUNK_MAP_COLS, CHUNK_MAP_ROWS, |row, col| {
+                tile_type(row * CHUNK_MAP_COLS + col)
+            }),
+            edges: Grid::new(edges, edges, 1.5),
+            normals: Grid::new(CHUNK_MAP_COLS + 1, CHUNK_MAP_ROWS + 1, [0.0, 0.0, 1.0]),
+        }
+    }
+
+    fn save_data(tiles: Grid<Tile>, chunk: ChunkMap) -> SaveData {
+        SaveData {
+            seed: WorldSeed::new(3),
+            tick: 42,
+            world_map: Arc::new(WorldMap { tiles }),
+            chunks: vec![(ChunkCoord::new(1, 2), Arc::new(chunk))],
+            character_position: [1.0, 2.0, 3.0],
+        }
+    }
+
+    // Start of the cells of the world grid, after its tag, length, width and height
+    fn first_tile(bytes: &[u8]) -> usize {
+        bytes
+            .windows(4)
+            .position(|tag| tag == WORLD_SECTION)
+            .unwrap()
+            + 4
+            + 8
+            + 8
+    }
+
+    #[test]
+    fn every_type_and_direction_round_trips() {
+        let tiles = Grid::from_fn(TILE_TYPES.len(), Direction::ALL.len() + 1, |row, col| {
+            tile(TILE_TYPES[col], Direction::ALL.get(row).copied())
+        });
+        let chunk = chunk(
+            |index| CHUNK_TILE_TYPES[index % CHUNK_TILE_TYPES.len()],
+            CHUNK_MAP_COLS + 1,
+        );
+        let data = save_data(tiles, chunk);
+
+        let loaded = decode(&encode(&data)).unwrap();
+        assert_eq!(loaded.world_map.tiles, data.world_map.tiles);
+        assert_eq!(loaded.chunks[0].1, data.chunks[0].1);
+    }
+
+    #[test]
+    fn corrupted_or_wrong_sized_saves_are_refused() {
+        let valid = || {
+            let tiles = Grid::new(2, 2, tile(TileType::GRASS, Some(Direction::E)));
+            save_data(tiles, chunk(|_| ChunkTileType::GRASS, CHUNK_MAP_COLS + 1))
+        };
+        let bytes = encode(&valid());
+        assert!(decode(&bytes).is_ok());
+
+        let mut unknown_type = bytes.clone();
+        unknown_type[first_tile(&bytes)] = TILE_TYPES.len() as u8;
+        assert!(decode(&unknown_type).is_err());
+
+        // Type, altitude and three floats come before the river direction
+        let mut unknown_direction = bytes.clone();
+        unknown_direction[first_tile(&bytes) + 14] = Direction::ALL.len() as u8;
+        assert!(decode(&unknown_direction).is_err());
+
+        let mut empty_world = valid();
+        empty_world.world_map = Arc::new(WorldMap {
+            tiles: Grid::new(0, 0, tile(TileType::WATER, None)),
+        });
+        assert!(decode(&encode(&empty_world)).is_err());
+
+        let small_chunk = save_data(
+            Grid::new(2, 2, tile(TileType::GRASS, None)),
+            chunk(|_| ChunkTileType::GRASS, 3),
+        );
+        let error = decode(&encode(&small_chunk)).err().unwrap();
+        assert!(error.to_string().contains("chunk edges"), "{}", error);
+    }
+
+    // A save of `data` with another version number and character section
+    fn with_character_section(data: &SaveData, version: u32, character: &[f32]) -> Vec<u8> {
+        let mut bytes = encode(data);
+        bytes[SAVE_MAGIC.len()..SAVE_MAGIC.len() + 4].copy_from_slice(&version.to_le_bytes());
+        // The character section comes last: tag, length and three floats
+        bytes.truncate(bytes.len() - (4 + 8 + 12));
+        let mut section = SaveWriter::new();
+        character.iter().for_each(|value| section.f32(*value));
+        let mut writer = SaveWriter { bytes };
+        writer.section(CHARACTER_SECTION, section);
+        writer.bytes
+    }
+
+    // Pretend version 1 only stored the character's x and y, version 2 added its height
+    fn add_character_height(sections: &mut Sections) -> io::Result<()> {
+        let character = sections
+            .get_mut(&CHARACTER_SECTION)
+            .ok_or_else(|| invalid("missing section CHAR"))?;
+        if character.len() != 8 {
+            return Err(invalid("version 1 characters have two coordinates"));
+        }
+        character.extend_from_slice(&0.0f32.to_le_bytes());
+        Ok(())
+    }
+
+    #[test]
+    fn older_saves_are_migrated_to_the_latest_version() {
+        let data = save_data(
+            Grid::new(2, 2, tile(TileType::GRASS, None)),
+            chunk(|_| ChunkTileType::GRASS, CHUNK_MAP_COLS + 1),
+        );
+        let migrations: [Migration; 1] = [add_character_height];
+
+        let version_1 = with_character_section(&data, 1, &[1.0, 2.0]);
+        let loaded = decode_with_migrations(&version_1, &migrations).unwrap();
+        assert_eq!(loaded.character_position, [1.0, 2.0, 0.0]);
+        assert_eq!(loaded.tick, data.tick);
+        assert_eq!(loaded.world_map.tiles, data.world_map.tiles);
+
+        // Saves of the latest version are read as they are
+        let version_2 = with_character_section(&data, 2, &[1.0, 2.0, 3.0]);
+        let loaded = decode_with_migrations(&version_2, &migrations).unwrap();
+        assert_eq!(loaded.character_position, [1.0, 2.0, 3.0]);
+
+        // A failing migration fails the load
+        let broken = with_character_section(&data, 1, &[1.0]);
+        assert!(decode_with_migrations(&broken, &migrations).is_err());
+    }
+
+    #[test]
+    fn versions_outside_the_known_range_are_refused() {
+        let data = save_data(
+            Grid::new(2, 2, tile(TileType::GRASS, None)),
+            chunk(|_| ChunkTileType::GRASS, CHUNK_MAP_COLS + 1),
+        );
+        let migrations: [Migration; 1] = [add_character_height];
+        for version in [0, 3, u32::MAX] {
+            let bytes = with_character_section(&data, version, &[1.0, 2.0, 3.0]);
+            let error = decode_with_migrations(&bytes, &migrations).err().unwrap();
+            assert!(error.to_string().contains("not supported"), "{}", error);
+        }
+        for version in [0, SAVE_VERSION + 1] {
+            let bytes = with_character_section(&data, version, &[1.0, 2.0, 3.0]);
+            assert!(decode(&bytes).is_err());
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+pub type WorldRng = ChaCha8Rng;
+
+// Salts used to give every generator its own independent stream
+const WORLD_MAP_SALT: u64 = 0x5745_4f52_4c44;
+const CHUNK_SALT: u64 = 0x0043_4855_4e4b;
+const EROSION_SALT: u64 = 0x4552_4f53_494f;
+const CLIMATE_SALT: u64 = 0x434c_494d_4154;
+const NOISE_SALT: u64 = 0x004e_4f49_5345;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct WorldSeed(pub u64);
+
+impl WorldSeed {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn random() -> Self {
+        Self(rand::random())
+    }
+
+    pub fn rng(&self) -> WorldRng {
+        WorldRng::seed_from_u64(self.0)
+    }
+
+    pub fn world_map_seed(&self) -> WorldSeed {
+        self.derive(WORLD_MAP_SALT)
+    }
+
+    pub fn erosion_seed(&self) -> WorldSeed {
+        self.derive(EROSION_SALT)
+    }
+
+    pub fn climate_seed(&self) -> WorldSeed {
+        self.derive(CLIMATE_SALT)
+    }
+
+    pub fn noise_seed(&self) -> WorldSeed {
+        self.derive(NOISE_SALT)
+    }
+
+    /// Sub-seed of the chunk at the given chunk coordinates, stable for a given world seed.
+    pub fn chunk_seed(&self, row: i32, col: i32) -> WorldSeed {
+        self.derive(CHUNK_SALT).cell_seed(row, col)
+    }
+
+    /// Sub-seed of a cell of any integer lattice, e.g. the gradients of a world-space noise.
+    pub fn cell_seed(&self, row: i32, col: i32) -> WorldSeed {
+        self.derive(row as u32 as u64).derive(col as u32 as u64)
+    }
+
+    fn derive(&self, salt: u64) -> WorldSeed {
+        WorldSeed(splitmix64(self.0 ^ splitmix64(salt)))
+    }
+}
+
+fn splitmix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::chunk_map::ChunkMap;
+    use crate::game::grid::Grid;
+    use crate::game::world_map::WorldMap;
+
+    #[test]
+    fn same_seed_generates_the_same_world_and_chunk() {
+        let generate = |seed: WorldSeed| {
+            let world = WorldMap::new(seed.world_map_seed(), 33, 33);
+            let chunk = ChunkMap::for_world_tile(&world, 16, 16, seed.chunk_seed(16, 16));
+            let altitudes = Grid::from_fn(33, 33, |row, col| world.tiles[(row, col)].altitude);
+            (altitudes, chunk.edges)
+        };
+
+        let seed = WorldSeed::new(7);
+        assert_eq!(generate(seed), generate(seed));
+        assert_ne!(generate(seed).0, generate(WorldSeed::new(8)).0);
+    }
+}
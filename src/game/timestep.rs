@@ -0,0 +1,101 @@
+use std::time::{Duration, Instant};
+
+/// Fixed-timestep accumulator: real time piles up and is consumed in ticks of exactly `tick`,
+/// so the simulation advances the same way whatever the speed of the machine.
+pub struct FixedTimestep {
+    tick: Duration,
+    accumulator: Duration,
+    last_advance: Instant,
+    max_frame_time: Duration,
+    max_ticks: u32,
+}
+
+impl FixedTimestep {
+    pub fn new(tick: Duration, max_frame_time: Duration, max_ticks: u32) -> Self {
+        Self {
+            tick,
+            accumulator: Duration::ZERO,
+            last_advance: Instant::now(),
+            max_frame_time,
+            max_ticks,
+        }
+    }
+
+    /// Length of a tick in seconds, the `dt` handed to the simulation.
+    pub fn dt(&self) -> f32 {
+        self.tick.as_secs_f32()
+    }
+
+    /// Adds the real time elapsed since the last call and returns how many ticks to run now.
+    pub fn advance(&mut self) -> u32 {
+        self.advance_to(Instant::now())
+    }
+
+    /// Same as `advance`, with the current time given.
+    pub fn advance_to(&mut self, now: Instant) -> u32 {
+        // After a stall (debugger, window drag, swapping...) don't try to simulate all of it
+        self.accumulator += (now - self.last_advance).min(self.max_frame_time);
+        self.last_advance = now;
+
+        let ticks = (self.accumulator.as_nanos() / self.tick.as_nanos()) as u32;
+        if ticks > self.max_ticks {
+            // Spiral of death guard: if ticks take longer than real time, running every late
+            // tick only makes the next frame later. Drop the backlog and fall behind instead
+            self.accumulator = Duration::ZERO;
+            return self.max_ticks;
+        }
+        self.accumulator -= self.tick * ticks;
+        ticks
+    }
+
+    /// Time left before the next tick is due.
+    pub fn until_next_tick(&self) -> Duration {
+        self.tick
+            .saturating_sub(self.accumulator + self.last_advance.elapsed())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    // 10 ms ticks, at most 250 ms of real time and `max_ticks` ticks per frame
+    fn timestep(max_ticks: u32) -> (FixedTimestep, Instant) {
+        let timestep = FixedTimestep::new(10 * MS, 250 * MS, max_ticks);
+        let start = timestep.last_advance;
+        (timestep, start)
+    }
+
+    #[test]
+    fn whole_ticks_are_run_and_the_rest_is_kept() {
+        let (mut timestep, start) = timestep(10);
+        assert_eq!(timestep.advance_to(start + 35 * MS), 3);
+        assert_eq!(timestep.accumulator, 5 * MS);
+
+        // The leftover 5 ms and 6 more make one tick
+        assert_eq!(timestep.advance_to(start + 41 * MS), 1);
+        assert_eq!(timestep.accumulator, MS);
+        assert_eq!(timestep.advance_to(start + 45 * MS), 0);
+        assert_eq!(timestep.accumulator, 5 * MS);
+        assert_eq!(timestep.dt(), 0.01);
+    }
+
+    #[test]
+    fn a_stall_only_counts_up_to_the_max_frame_time() {
+        let (mut timestep, start) = timestep(100);
+        assert_eq!(timestep.advance_to(start + 10_000 * MS), 25);
+        assert_eq!(timestep.accumulator, Duration::ZERO);
+    }
+
+    #[test]
+    fn too_many_late_ticks_are_capped_and_dropped() {
+        let (mut timestep, start) = timestep(5);
+        assert_eq!(timestep.advance_to(start + 87 * MS), 5);
+        // The backlog is gone, the next frame starts from scratch
+        assert_eq!(timestep.accumulator, Duration::ZERO);
+        assert_eq!(timestep.advance_to(start + 99 * MS), 1);
+        assert_eq!(timestep.accumulator, 2 * MS);
+    }
+}
@@ -1,9 +1,17 @@
 use crate::constants::{
-    CA_ITER, DS_CENTER_INIT, DS_EDGE_INIT, DS_ROUGHNESS, N, WORLD_MAP_COLS, WORLD_MAP_ROWS,
+    CA_ITER, DS_CENTER_INIT, DS_EDGE_INIT, DS_ROUGHNESS, EROSION_THERMAL_ITER, MIN_WORLD_MAP_SIZE,
+    WORLD_EROSION_DROPLETS, WORLD_EROSION_TALUS, WORLD_NOISE_AMPLITUDE, WORLD_NOISE_FREQUENCY,
+    WORLD_NOISE_OCTAVES, WORLD_NOISE_WARP,
 };
+use crate::game::climate::Climate;
+use crate::game::erosion::{HydraulicErosion, ThermalErosion};
+use crate::game::grid::{Direction, Grid};
+use crate::game::hydrology::Hydrology;
+use crate::game::noise::{DomainWarp, Fractal, Noise, Simplex, Value};
+use crate::game::seed::{WorldRng, WorldSeed};
 use rand::Rng;
 
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, PartialEq, Debug)]
 pub enum TileType {
     WATER,
     GRASS,
@@ -11,43 +19,72 @@ pub enum TileType {
     HILLS,
     MOUNTAINS,
     HIGHLANDS,
+    DESERT,
+    STEPPE,
+    TUNDRA,
+    SWAMP,
+    JUNGLE,
+    SNOW,
+    LAKE,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Debug)]
 pub struct Tile {
     pub tile_type: TileType,
     pub altitude: u8,
+    pub temperature: f32,
+    pub moisture: f32,
+    /// Water carried by the river crossing this tile, 0 if there is none.
+    pub river_flow: f32,
+    pub river_direction: Option<Direction>,
 }
 
 pub struct WorldMap {
-    pub tiles: [[Tile; WORLD_MAP_COLS]; WORLD_MAP_ROWS],
+    pub tiles: Grid<Tile>,
 }
 
 impl WorldMap {
-    pub fn new() -> Self {
-        let tiles = Self::coast_cellular_automata(Self::give_types(Self::diamond_square()));
+    /// Panics if a side is shorter than `MIN_WORLD_MAP_SIZE`.
+    pub fn new(seed: WorldSeed, width: usize, height: usize) -> Self {
+        assert!(
+            width >= MIN_WORLD_MAP_SIZE && height >= MIN_WORLD_MAP_SIZE,
+            "A {width}x{height} world map is smaller than {MIN_WORLD_MAP_SIZE}x{MIN_WORLD_MAP_SIZE}"
+        );
+        let mut altitudes = Self::diamond_square(seed, width, height);
+        Self::add_hills(&mut altitudes, seed);
+        ThermalErosion::new(EROSION_THERMAL_ITER, WORLD_EROSION_TALUS).erode(&mut altitudes);
+        HydraulicErosion::new(WORLD_EROSION_DROPLETS).erode(&mut altitudes, seed.erosion_seed());
+        let tiles = Self::coast_cellular_automata(Self::give_types(&altitudes));
+        let tiles = Self::give_biomes(tiles, seed);
+        let tiles = Self::give_hydrology(tiles, &altitudes);
         WorldMap { tiles }
     }
 
-    pub fn diamond_square() -> [[f32; WORLD_MAP_COLS]; WORLD_MAP_ROWS] {
-        let mut rng = rand::rng();
+    /// Runs diamond-square on the smallest 2^n + 1 square that fits the requested size and
+    /// crops the centered `width` x `height` window out of it.
+    pub fn diamond_square(seed: WorldSeed, width: usize, height: usize) -> Grid<f32> {
+        let mut rng = seed.rng();
         let rand_cap = DS_ROUGHNESS;
-        const MAPSIZE: usize = WORLD_MAP_ROWS;
-        let mut tiles: [[f32; MAPSIZE]; MAPSIZE] = [[f32::NAN; MAPSIZE]; MAPSIZE];
-        tiles[0][0] = DS_EDGE_INIT;
-        tiles[0][MAPSIZE - 1] = DS_EDGE_INIT;
-        tiles[MAPSIZE - 1][0] = DS_EDGE_INIT;
-        tiles[MAPSIZE - 1][MAPSIZE - 1] = DS_EDGE_INIT;
+        let mut n = 1;
+        while 2_usize.pow(n as u32) + 1 < width.max(height) {
+            n += 1;
+        }
+        let mapsize = 2_usize.pow(n as u32) + 1;
+        let mut tiles = Grid::new(mapsize, mapsize, f32::NAN);
+        tiles[(0, 0)] = DS_EDGE_INIT;
+        tiles[(0, mapsize - 1)] = DS_EDGE_INIT;
+        tiles[(mapsize - 1, 0)] = DS_EDGE_INIT;
+        tiles[(mapsize - 1, mapsize - 1)] = DS_EDGE_INIT;
 
         let diamond = |l: usize,
                        pos: (usize, usize),
                        utility: &mut Vec<(usize, usize)>,
-                       tiles: &mut [[f32; MAPSIZE]; MAPSIZE],
-                       rng: &mut rand::rngs::ThreadRng| {
-            tiles[pos.0][pos.1] = (tiles[pos.0 + l][pos.1 + l]
-                + tiles[pos.0 + l][pos.1 - l]
-                + tiles[pos.0 - l][pos.1 + l]
-                + tiles[pos.0 - l][pos.1 - l])
+                       tiles: &mut Grid<f32>,
+                       rng: &mut WorldRng| {
+            tiles[(pos.0, pos.1)] = (tiles[(pos.0 + l, pos.1 + l)]
+                + tiles[(pos.0 + l, pos.1 - l)]
+                + tiles[(pos.0 - l, pos.1 + l)]
+                + tiles[(pos.0 - l, pos.1 - l)])
                 / 4.0
                 + rng.random_range(-rand_cap..=rand_cap);
             utility.push((pos.0 + l / 2, pos.1 + l / 2));
@@ -56,39 +93,36 @@ impl WorldMap {
             utility.push((pos.0 - l / 2, pos.1 - l / 2));
         };
 
-        let square = |l: usize,
-                      pos: (usize, usize),
-                      tiles: &mut [[f32; MAPSIZE]; MAPSIZE],
-                      rng: &mut rand::rngs::ThreadRng| {
+        let square = |l: usize, pos: (usize, usize), tiles: &mut Grid<f32>, rng: &mut WorldRng| {
             let mut sum = 0.0;
             let mut count = 0;
 
             let directions = [
-                (l as isize, 0 as isize),
-                (-(l as isize), 0 as isize),
-                (0 as isize, l as isize),
-                (0 as isize, -(l as isize)),
+                (l as isize, 0),
+                (-(l as isize), 0),
+                (0, l as isize),
+                (0, -(l as isize)),
             ];
             for (dx, dy) in directions.iter() {
                 let x = pos.0 as isize + dx;
                 let y = pos.1 as isize + dy;
 
-                if x >= 0 && y >= 0 && (x as usize) < MAPSIZE && (y as usize) < MAPSIZE {
-                    sum += tiles[x as usize][y as usize];
+                if tiles.in_bounds(x, y) {
+                    sum += tiles[(x as usize, y as usize)];
                     count += 1;
                 }
             }
-            tiles[pos.0][pos.1] = sum / count as f32 + rng.random_range(-rand_cap..=rand_cap);
+            tiles[(pos.0, pos.1)] = sum / count as f32 + rng.random_range(-rand_cap..=rand_cap);
         };
 
-        let mut positions: Vec<(usize, usize)> = vec![(MAPSIZE / 2, MAPSIZE / 2)];
+        let mut positions: Vec<(usize, usize)> = vec![(mapsize / 2, mapsize / 2)];
         let mut utility: Vec<(usize, usize)> = vec![];
         let mut l: usize;
-        for iter in 1..=N {
-            l = MAPSIZE / 2_usize.pow(iter as u32);
+        for iter in 1..=n {
+            l = mapsize / 2_usize.pow(iter as u32);
             for pos in positions.iter() {
                 diamond(l, *pos, &mut utility, &mut tiles, &mut rng);
-                tiles[MAPSIZE / 2][MAPSIZE / 2] = DS_CENTER_INIT;
+                tiles[(mapsize / 2, mapsize / 2)] = DS_CENTER_INIT;
             }
             for pos in positions.iter() {
                 square(l, (pos.0 + l, pos.1), &mut tiles, &mut rng);
@@ -99,62 +133,93 @@ impl WorldMap {
             positions = utility;
             utility = vec![];
         }
-        tiles
+
+        let row_offset = (mapsize - height) / 2;
+        let col_offset = (mapsize - width) / 2;
+        Grid::from_fn(width, height, |row, col| {
+            tiles[(row + row_offset, col + col_offset)]
+        })
     }
 
-    fn give_types(
-        altitudes: [[f32; WORLD_MAP_COLS]; WORLD_MAP_ROWS],
-    ) -> [[Tile; WORLD_MAP_COLS]; WORLD_MAP_ROWS] {
-        let mut tiles = [[Tile {
-            altitude: 0,
-            tile_type: TileType::WATER,
-        }; WORLD_MAP_COLS]; WORLD_MAP_ROWS];
-
-        for row in 0..WORLD_MAP_ROWS {
-            for col in 0..WORLD_MAP_COLS {
-                let altitude = altitudes[row][col] as u8;
+    // Rounded hills on top of diamond-square, the warp breaks up its axis-aligned creases
+    fn add_hills(altitudes: &mut Grid<f32>, seed: WorldSeed) {
+        let seed = seed.noise_seed();
+        let hills = DomainWarp::new(
+            Fractal::billow(Value::new(seed), WORLD_NOISE_OCTAVES)
+                .frequency(WORLD_NOISE_FREQUENCY)
+                .lacunarity(2.5)
+                .gain(0.4),
+            Fractal::fbm(Simplex::new(seed.noise_seed()), 2).frequency(WORLD_NOISE_FREQUENCY),
+            WORLD_NOISE_WARP,
+        );
+        for row in 0..altitudes.height() {
+            for col in 0..altitudes.width() {
+                altitudes[(row, col)] += hills.get(col as f32, row as f32) * WORLD_NOISE_AMPLITUDE;
+            }
+        }
+    }
+
+    fn give_types(altitudes: &Grid<f32>) -> Grid<Tile> {
+        let mut tiles = Grid::new(
+            altitudes.width(),
+            altitudes.height(),
+            Tile {
+                altitude: 0,
+                tile_type: TileType::WATER,
+                temperature: 0.0,
+                moisture: 0.0,
+                river_flow: 0.0,
+                river_direction: None,
+            },
+        );
+
+        for row in 0..altitudes.height() {
+            for col in 0..altitudes.width() {
+                let altitude = altitudes[(row, col)] as u8;
                 let tile_type = match altitude {
+                    // Lowlands get their final type from the climate in give_biomes
                     0 => TileType::WATER,
-                    1..=60 => TileType::GRASS,
-                    61..=90 => TileType::WOODS,
+                    1..=90 => TileType::GRASS,
                     91..=110 => TileType::HILLS,
                     111..=130 => TileType::MOUNTAINS,
                     _ => TileType::HIGHLANDS,
                 };
-                tiles[row][col] = Tile {
+                tiles[(row, col)] = Tile {
                     tile_type,
                     altitude,
+                    temperature: 0.0,
+                    moisture: 0.0,
+                    river_flow: 0.0,
+                    river_direction: None,
                 };
             }
         }
         tiles
     }
 
-    pub fn coast_cellular_automata(
-        mut tiles: [[Tile; WORLD_MAP_COLS]; WORLD_MAP_ROWS],
-    ) -> [[Tile; WORLD_MAP_COLS]; WORLD_MAP_ROWS] {
+    pub fn coast_cellular_automata(mut tiles: Grid<Tile>) -> Grid<Tile> {
         let mut wall_counter = 0;
         for _ in 0..CA_ITER {
-            let mut temp_tiles = tiles;
-            for row in 1..WORLD_MAP_ROWS - 1 {
-                for col in 1..WORLD_MAP_COLS - 1 {
+            let mut temp_tiles = tiles.clone();
+            for row in 1..tiles.height().saturating_sub(1) {
+                for col in 1..tiles.width().saturating_sub(1) {
                     for i in row - 1..=row + 1 {
                         for j in col - 1..=col + 1 {
                             if i == row && j == col {
                                 continue;
                             }
-                            if tiles[i][j].tile_type == TileType::WATER {
+                            if tiles[(i, j)].tile_type == TileType::WATER {
                                 wall_counter += 1;
                             }
                         }
                     }
-                    if wall_counter > 4 && temp_tiles[row][col].tile_type == TileType::GRASS {
-                        temp_tiles[row][col].altitude = 0;
-                        temp_tiles[row][col].tile_type = TileType::WATER;
+                    if wall_counter > 4 && temp_tiles[(row, col)].tile_type == TileType::GRASS {
+                        temp_tiles[(row, col)].altitude = 0;
+                        temp_tiles[(row, col)].tile_type = TileType::WATER;
                     }
-                    if wall_counter < 3 && temp_tiles[row][col].tile_type == TileType::WATER {
-                        temp_tiles[row][col].altitude = 1;
-                        temp_tiles[row][col].tile_type = TileType::GRASS;
+                    if wall_counter < 3 && temp_tiles[(row, col)].tile_type == TileType::WATER {
+                        temp_tiles[(row, col)].altitude = 1;
+                        temp_tiles[(row, col)].tile_type = TileType::GRASS;
                     }
                     wall_counter = 0;
                 }
@@ -163,4 +228,73 @@ impl WorldMap {
         }
         tiles
     }
+
+    fn give_biomes(mut tiles: Grid<Tile>, seed: WorldSeed) -> Grid<Tile> {
+        let climate = Climate::new(&tiles, seed);
+
+        for row in 0..tiles.height() {
+            for col in 0..tiles.width() {
+                let tile = &mut tiles[(row, col)];
+                tile.temperature = climate.temperature[(row, col)];
+                tile.moisture = climate.moisture[(row, col)];
+
+                let biome = climate.biome(row, col);
+                tile.tile_type = match tile.tile_type {
+                    TileType::GRASS => biome,
+                    TileType::HILLS | TileType::MOUNTAINS | TileType::HIGHLANDS
+                        if biome == TileType::SNOW =>
+                    {
+                        TileType::SNOW
+                    }
+                    tile_type => tile_type,
+                };
+            }
+        }
+        tiles
+    }
+
+    fn give_hydrology(mut tiles: Grid<Tile>, altitudes: &Grid<f32>) -> Grid<Tile> {
+        let hydrology = Hydrology::new(altitudes, &tiles);
+
+        for row in 0..tiles.height() {
+            for col in 0..tiles.width() {
+                let tile = &mut tiles[(row, col)];
+                if tile.tile_type == TileType::WATER {
+                    continue;
+                }
+                if hydrology.is_lake(altitudes, row, col) {
+                    tile.tile_type = TileType::LAKE;
+                }
+                if hydrology.river[(row, col)] {
+                    tile.river_flow = hydrology.flow[(row, col)];
+                    tile.river_direction = hydrology.direction[(row, col)];
+                }
+            }
+        }
+        tiles
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn smallest_world_map_is_generated() {
+        let world = WorldMap::new(WorldSeed::new(1), MIN_WORLD_MAP_SIZE, MIN_WORLD_MAP_SIZE);
+        assert_eq!(world.tiles.width(), MIN_WORLD_MAP_SIZE);
+        assert_eq!(world.tiles.height(), MIN_WORLD_MAP_SIZE);
+    }
+
+    #[test]
+    #[should_panic(expected = "A 0x0 world map is smaller than 3x3")]
+    fn empty_world_map_is_refused() {
+        WorldMap::new(WorldSeed::new(1), 0, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "A 17x2 world map is smaller than 3x3")]
+    fn too_thin_world_map_is_refused() {
+        WorldMap::new(WorldSeed::new(1), 17, 2);
+    }
 }
@@ -14,6 +14,7 @@ pub struct Camera {
     alpha: f32,
 }
 
+#[derive(Default)]
 pub struct CameraMovement {
     pub left: bool,
     pub right: bool,
@@ -32,14 +33,13 @@ pub struct CameraUniform {
 
 impl CameraMovement {
     pub fn new() -> Self {
-        Self {
-            left: false,
-            right: false,
-            zoom: false,
-            unzoom: false,
-            pz: false,
-            mz: false,
-        }
+        Self::default()
+    }
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -49,7 +49,7 @@ impl Camera {
             eye: (15.0, 0.0, 10.0).into(),
             target: (0.0, 0.0, 0.0).into(),
             up: Vector3::unit_z(),
-            aspect: 3 as f32 / 2 as f32,
+            aspect: 3.0 / 2.0,
             fovy: 20.0,
             znear: 0.1,
             zfar: 100.0,
@@ -62,7 +62,7 @@ impl Camera {
         let view = cgmath::Matrix4::look_at_rh(self.eye, self.target, self.up);
         let proj = cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar);
 
-        return OPENGL_TO_WGPU_MATRIX * proj * view;
+        OPENGL_TO_WGPU_MATRIX * proj * view
     }
 
     pub fn get_camera_uniform(&self) -> CameraUniform {
@@ -72,13 +72,26 @@ impl Camera {
         }
     }
 
+    /// Puts the camera at `offset` from `target`, `update` keeps that offset afterwards.
+    pub fn place(&mut self, target: [f32; 3], offset: [f32; 3]) {
+        self.target = target.into();
+        self.eye = Point3::new(
+            target[0] + offset[0],
+            target[1] + offset[1],
+            target[2] + offset[2],
+        );
+        self.alpha = offset[1].atan2(offset[0]);
+    }
+
     pub fn update(&mut self, target: [f32; 3]) {
-        self.alpha = self.alpha % (2.0 * std::f32::consts::PI);
+        self.alpha %= 2.0 * std::f32::consts::PI;
         let mut rx = self.eye.x - self.target.x;
         let mut ry = self.eye.y - self.target.y;
+        let rz = self.eye.z - self.target.z;
         let r = f32::sqrt(rx * rx + ry * ry);
 
         self.target = target.into();
+        self.eye.z = rz + self.target.z;
 
         let targetx = self.target.x;
         let targety = self.target.y;
@@ -107,14 +120,14 @@ impl Camera {
             self.eye.y = ry * factor + targety;
         }
         if self.movement.pz {
-            self.eye.z *= 1.05;
+            self.eye.z = rz * 1.05 + self.target.z;
         } else if self.movement.mz {
-            self.eye.z *= 0.95;
+            self.eye.z = rz * 0.95 + self.target.z;
         }
     }
 
     pub fn get_eye_target_xy_direction(&self) -> Vector2<f32> {
-        return Vector2::new((self.target - self.eye).x, (self.target - self.eye).y).normalize();
+        Vector2::new((self.target - self.eye).x, (self.target - self.eye).y).normalize()
     }
 
     pub fn get_eye_target_xy_direction_perp(&self, negative: bool) -> Vector2<f32> {
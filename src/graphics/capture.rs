@@ -0,0 +1,335 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::constants::{CAPTURE_BUFFER_POOL, CAPTURE_SAVE_QUEUE, SCREENSHOT_DIR};
+use crate::graphics::render_target;
+
+// A frame copied into `buffer`, waiting for the GPU to finish before it can be read
+struct PendingFrame {
+    id: u64,
+    mapping: bool,
+    buffer: wgpu::Buffer,
+    path: PathBuf,
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+}
+
+// Raw bytes of a mapped frame, turned into a PNG on the saver thread
+struct FrameBytes {
+    bytes: Vec<u8>,
+    path: PathBuf,
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+}
+
+/// Saves rendered frames to PNG without stalling the frame: the copy is recorded with the
+/// frame, read back a few frames later when the GPU is done with it, and encoded on its own
+/// thread. When the GPU or the encoding can't keep up, frames are skipped instead of piling
+/// up in memory.
+pub struct FrameCapture {
+    device: wgpu::Device,
+    screenshot_requested: bool,
+    // Folder and next frame number of the running burst
+    burst: Option<(PathBuf, u32)>,
+    next_id: u64,
+    pending: Vec<PendingFrame>,
+    // Unmapped readback buffers and the frame size they were made for, reused by the next
+    // frames of that size
+    free_buffers: Vec<(u32, u32, wgpu::Buffer)>,
+    skips: SkipCount,
+    mapped_sender: mpsc::Sender<(u64, Result<(), wgpu::BufferAsyncError>)>,
+    mapped_receiver: mpsc::Receiver<(u64, Result<(), wgpu::BufferAsyncError>)>,
+    saver_sender: Option<mpsc::SyncSender<FrameBytes>>,
+    saver: Option<thread::JoinHandle<()>>,
+}
+
+impl FrameCapture {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let (mapped_sender, mapped_receiver) = mpsc::channel();
+        let (saver_sender, saver_receiver) = mpsc::sync_channel::<FrameBytes>(CAPTURE_SAVE_QUEUE);
+        let saver = thread::spawn(move || {
+            for frame in saver_receiver {
+                let image = render_target::buffer_to_image(
+                    &frame.bytes,
+                    frame.width,
+                    frame.height,
+                    frame.format,
+                );
+                let saved = frame
+                    .path
+                    .parent()
+                    .map_or(Ok(()), std::fs::create_dir_all)
+                    .map_err(image::ImageError::IoError)
+                    .and_then(|_| image.save(&frame.path));
+                match saved {
+                    Ok(()) => eprintln!("Frame saved to {}", frame.path.display()),
+                    Err(error) => eprintln!("Saving {} failed: {}", frame.path.display(), error),
+                }
+            }
+        });
+
+        Self {
+            device: device.clone(),
+            screenshot_requested: false,
+            burst: None,
+            next_id: 0,
+            pending: Vec::new(),
+            free_buffers: Vec::new(),
+            skips: SkipCount::default(),
+            mapped_sender,
+            mapped_receiver,
+            saver_sender: Some(saver_sender),
+            saver: Some(saver),
+        }
+    }
+
+    /// Saves the next frame as `screenshot-<timestamp>.png`.
+    pub fn request_screenshot(&mut self) {
+        self.screenshot_requested = true;
+    }
+
+    /// Starts or stops saving every frame as `burst-<timestamp>/frame-<number>.png`.
+    pub fn toggle_burst(&mut self) {
+        self.burst = match self.burst {
+            Some(_) => {
+                eprintln!(
+                    "Burst capture stopped, {} frames skipped",
+                    self.skips.reset()
+                );
+                None
+            }
+            None => {
+                let folder = PathBuf::from(SCREENSHOT_DIR).join(format!("burst-{}", timestamp()));
+                eprintln!("Burst capture into {}", folder.display());
+                self.skips.reset();
+                Some((folder, 0))
+            }
+        };
+    }
+
+    /// Records the copy of `texture` if this frame has to be saved. Call before submitting
+    /// the encoder, then `after_submit`.
+    pub fn record(&mut self, encoder: &mut wgpu::CommandEncoder, texture: &wgpu::Texture) {
+        let mut paths = Vec::new();
+        if self.screenshot_requested {
+            self.screenshot_requested = false;
+            paths.push(
+                PathBuf::from(SCREENSHOT_DIR).join(format!("screenshot-{}.png", timestamp())),
+            );
+        }
+        if let Some((folder, frame)) = &mut self.burst {
+            paths.push(folder.join(format!("frame-{:05}.png", frame)));
+            *frame += 1;
+        }
+        if paths.is_empty() {
+            return;
+        }
+        if !texture.usage().contains(wgpu::TextureUsages::COPY_SRC) {
+            eprintln!("This surface can't be copied, no frame capture");
+            self.burst = None;
+            return;
+        }
+
+        let (width, height) = (texture.width(), texture.height());
+        for path in paths {
+            // Every buffer of the pool is still waiting for the GPU
+            if !pool_has_room(self.pending.len()) {
+                self.skip(&path);
+                continue;
+            }
+            let buffer = self.take_buffer(width, height);
+            render_target::copy_into_buffer(encoder, texture, &buffer);
+            self.pending.push(PendingFrame {
+                id: self.next_id,
+                mapping: false,
+                buffer,
+                path,
+                width,
+                height,
+                format: texture.format(),
+            });
+            self.next_id += 1;
+        }
+    }
+
+    /// Asks for the frames recorded this frame to be mapped, and hands the ones that got
+    /// mapped since the last call to the saver thread. Never waits for the GPU.
+    pub fn after_submit(&mut self) {
+        for frame in self.pending.iter_mut().filter(|frame| !frame.mapping) {
+            let (id, mapped_sender) = (frame.id, self.mapped_sender.clone());
+            frame
+                .buffer
+                .slice(..)
+                .map_async(wgpu::MapMode::Read, move |result| {
+                    let _ = mapped_sender.send((id, result));
+                });
+            frame.mapping = true;
+        }
+        self.device.poll(wgpu::Maintain::Poll);
+        self.send_mapped(false);
+    }
+
+    // Hands the mapped frames to the saver thread and puts their buffers back in the pool.
+    // Without `wait`, frames that don't fit in the saver queue are skipped
+    fn send_mapped(&mut self, wait: bool) {
+        while let Ok((id, result)) = self.mapped_receiver.try_recv() {
+            let Some(index) = self.pending.iter().position(|frame| frame.id == id) else {
+                continue;
+            };
+            let frame = self.pending.swap_remove(index);
+            if let Err(error) = result {
+                eprintln!("Reading back {} failed: {}", frame.path.display(), error);
+                continue;
+            }
+            let bytes = frame.buffer.slice(..).get_mapped_range().to_vec();
+            frame.buffer.unmap();
+            self.free_buffers
+                .push((frame.width, frame.height, frame.buffer));
+
+            let Some(saver_sender) = &self.saver_sender else {
+                continue;
+            };
+            let bytes = FrameBytes {
+                bytes,
+                path: frame.path,
+                width: frame.width,
+                height: frame.height,
+                format: frame.format,
+            };
+            if let Err(bytes) = queue_frame(saver_sender, bytes, wait) {
+                self.skip(&bytes.path);
+            }
+        }
+    }
+
+    // A free buffer for a frame of that size, or a new one
+    fn take_buffer(&mut self, width: u32, height: u32) -> wgpu::Buffer {
+        // Buffers of an older window size won't fit again
+        self.free_buffers
+            .retain(|(buffer_width, buffer_height, _)| {
+                (*buffer_width, *buffer_height) == (width, height)
+            });
+        match self.free_buffers.pop() {
+            Some((_, _, buffer)) => buffer,
+            None => render_target::create_readback_buffer(&self.device, width, height),
+        }
+    }
+
+    // Only the first skip is logged, the total comes when the burst stops
+    fn skip(&mut self, path: &Path) {
+        if self.skips.skip() {
+            eprintln!(
+                "Frame capture can't keep up, skipping frames from {}",
+                path.display()
+            );
+        }
+    }
+}
+
+impl Drop for FrameCapture {
+    // Waits for the frames the GPU is still copying and lets the saver thread write them
+    // along with what it already got
+    fn drop(&mut self) {
+        if self.pending.iter().any(|frame| frame.mapping) {
+            self.device.poll(wgpu::Maintain::Wait);
+            self.send_mapped(true);
+        }
+        self.saver_sender.take();
+        if let Some(saver) = self.saver.take() {
+            saver.join().unwrap();
+        }
+    }
+}
+
+// Frames skipped since the burst started
+#[derive(Default)]
+struct SkipCount {
+    skipped: u32,
+}
+
+impl SkipCount {
+    // Counts a skipped frame, true if it is the first one
+    fn skip(&mut self) -> bool {
+        self.skipped += 1;
+        self.skipped == 1
+    }
+
+    // The number of skipped frames, counting starts over
+    fn reset(&mut self) -> u32 {
+        std::mem::take(&mut self.skipped)
+    }
+}
+
+// Whether a readback buffer is left for one more frame
+fn pool_has_room(pending: usize) -> bool {
+    pending < CAPTURE_BUFFER_POOL
+}
+
+// Puts a frame in the saver queue, giving it back if the queue is full and `wait` is off, or
+// if the saver is gone
+fn queue_frame<T>(sender: &mpsc::SyncSender<T>, frame: T, wait: bool) -> Result<(), T> {
+    match wait {
+        true => sender.send(frame).map_err(|error| error.0),
+        false => sender.try_send(frame).map_err(|error| match error {
+            mpsc::TrySendError::Full(frame) | mpsc::TrySendError::Disconnected(frame) => frame,
+        }),
+    }
+}
+
+// Seconds and milliseconds since the epoch, sorts in capture order
+fn timestamp() -> String {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    format!(
+        "{}-{:03}",
+        since_epoch.as_secs(),
+        since_epoch.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_are_skipped_once_every_buffer_is_pending() {
+        let mut skips = SkipCount::default();
+        let mut pending = 0;
+        let mut logged = 0;
+        for _ in 0..CAPTURE_BUFFER_POOL + 3 {
+            if pool_has_room(pending) {
+                pending += 1;
+            } else if skips.skip() {
+                logged += 1;
+            }
+        }
+        assert_eq!(pending, CAPTURE_BUFFER_POOL);
+        assert_eq!(logged, 1);
+        assert_eq!(skips.reset(), 3);
+        assert_eq!(skips.reset(), 0);
+    }
+
+    #[test]
+    fn full_save_queue_hands_the_frame_back_without_waiting() {
+        let (sender, receiver) = mpsc::sync_channel(CAPTURE_SAVE_QUEUE);
+        let mut skips = SkipCount::default();
+        for frame in 0..CAPTURE_SAVE_QUEUE + 2 {
+            if let Err(skipped) = queue_frame(&sender, frame, false) {
+                assert!(skipped >= CAPTURE_SAVE_QUEUE);
+                skips.skip();
+            }
+        }
+        assert_eq!(skips.reset(), 2);
+        let queued: Vec<_> = receiver.try_iter().collect();
+        assert_eq!(queued, (0..CAPTURE_SAVE_QUEUE).collect::<Vec<_>>());
+
+        // Once the saver is gone, even a waiting send gives the frame back
+        drop(receiver);
+        assert_eq!(queue_frame(&sender, 7, true), Err(7));
+    }
+}
@@ -1,62 +1,125 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
 use crate::constants::{CHUNK_MAP_COLS, CHUNK_MAP_ROWS, CHUNK_TILE_SIZE};
-use crate::game;
-use crate::graphics::model::ChunkTile;
+use crate::game::chunk_manager::ChunkCoord;
+use crate::game::chunk_map::ChunkMap;
+use crate::graphics::model::{ChunkTile, Instance};
 use crate::graphics::vertex::TexVertex;
-use cgmath::InnerSpace;
 use wgpu::util::DeviceExt;
 
-pub struct ChunkMapTiles {
-    pub vertices: Vec<TexVertex>,
+/// GPU side of a single loaded chunk.
+pub struct ChunkMesh {
     pub num_vertices: u32,
     pub vertex_buffer: wgpu::Buffer,
+    /// Min and max corners of the mesh.
+    pub bounds: [[f32; 3]; 2],
+}
+
+pub struct ChunkMapTiles {
+    chunks: HashMap<ChunkCoord, ChunkMesh>,
+    /// Identity transform for the chunk pipeline, the meshes are already in world space.
+    pub instance_buffer: wgpu::Buffer,
 }
 
 impl ChunkMapTiles {
-    pub fn new(device: &wgpu::Device, chunk_tiles: &game::chunk_map::ChunkMap) -> Self {
-        println!("This prints");
-        let vertices = Self::initialize_map_vertices(&chunk_tiles.edges, &chunk_tiles.tile_types);
-        let num_vertices = vertices.len() as u32;
+    pub fn new(device: &wgpu::Device) -> Self {
+        Self {
+            chunks: HashMap::new(),
+            instance_buffer: Instance::identity().create_buffer(device),
+        }
+    }
+
+    /// Uploads the chunks that just streamed in and frees the buffers of the ones that left.
+    pub fn sync(&mut self, device: &wgpu::Device, loaded: &[(ChunkCoord, Arc<ChunkMap>)]) {
+        self.chunks.retain(|coord, mesh| {
+            let still_loaded = loaded.iter().any(|(loaded_coord, _)| loaded_coord == coord);
+            if !still_loaded {
+                mesh.vertex_buffer.destroy();
+            }
+            still_loaded
+        });
+
+        for (coord, chunk) in loaded {
+            if !self.chunks.contains_key(coord) {
+                self.chunks
+                    .insert(*coord, Self::create_mesh(device, *coord, chunk));
+            }
+        }
+    }
+
+    pub fn meshes(&self) -> impl Iterator<Item = &ChunkMesh> {
+        self.chunks.values()
+    }
+
+    /// Min and max corners around all the loaded chunks, `None` before the first one.
+    pub fn bounds(&self) -> Option<[[f32; 3]; 2]> {
+        self.meshes()
+            .map(|mesh| mesh.bounds)
+            .reduce(|[min, max], [mesh_min, mesh_max]| {
+                [
+                    [0, 1, 2].map(|i| min[i].min(mesh_min[i])),
+                    [0, 1, 2].map(|i| max[i].max(mesh_max[i])),
+                ]
+            })
+    }
+
+    fn create_mesh(device: &wgpu::Device, coord: ChunkCoord, chunk: &ChunkMap) -> ChunkMesh {
+        let vertices = Self::initialize_map_vertices(coord, chunk);
 
         let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("GChunkMap vertex buffer"),
+            label: Some(&format!("GChunkMap vertex buffer {:?}", coord)),
             contents: bytemuck::cast_slice(&vertices),
             usage: wgpu::BufferUsages::VERTEX,
         });
 
-        Self {
-            vertices,
-            num_vertices,
+        let [origin_x, origin_y] = coord.origin();
+        let min_height = chunk.edges.iter().copied().fold(f32::INFINITY, f32::min);
+        let max_height = chunk
+            .edges
+            .iter()
+            .copied()
+            .fold(f32::NEG_INFINITY, f32::max);
+        let bounds = [
+            [
+                origin_x * CHUNK_TILE_SIZE,
+                origin_y * CHUNK_TILE_SIZE,
+                min_height,
+            ],
+            [
+                (origin_x + CHUNK_MAP_COLS as f32) * CHUNK_TILE_SIZE,
+                (origin_y + CHUNK_MAP_ROWS as f32) * CHUNK_TILE_SIZE,
+                max_height,
+            ],
+        ];
+
+        ChunkMesh {
+            num_vertices: vertices.len() as u32,
             vertex_buffer,
+            bounds,
         }
     }
 
-    fn initialize_map_vertices(
-        heights: &Vec<Vec<f32>>,
-        types: &Vec<Vec<game::chunk_map::ChunkTileType>>,
-    ) -> Vec<TexVertex> {
-        println!("This doesnt print");
+    fn initialize_map_vertices(coord: ChunkCoord, chunk: &ChunkMap) -> Vec<TexVertex> {
         let mut vertices = Vec::new();
         let mut normals = vec![vec![[0.0, 0.0, 0.0]; CHUNK_MAP_COLS + 1]; CHUNK_MAP_ROWS + 1];
         let mut positions = vec![vec![[0.0, 0.0, 0.0]; CHUNK_MAP_COLS + 1]; CHUNK_MAP_ROWS + 1];
 
-        let first_row_coord = -1.0 + (2.0 - CHUNK_TILE_SIZE * CHUNK_MAP_ROWS as f32) / 2.0;
-        let first_col_coord = -1.0 + (2.0 - CHUNK_TILE_SIZE * CHUNK_MAP_COLS as f32) / 2.0;
+        let [origin_x, origin_y] = coord.origin();
+        let first_row_coord = origin_y * CHUNK_TILE_SIZE;
+        let first_col_coord = origin_x * CHUNK_TILE_SIZE;
 
         for row in 0..=CHUNK_MAP_ROWS {
             for col in 0..=CHUNK_MAP_COLS {
                 let current_row_coord = first_row_coord + CHUNK_TILE_SIZE * row as f32;
                 let current_col_coord = first_col_coord + CHUNK_TILE_SIZE * col as f32;
-                positions[row][col] = [current_col_coord, current_row_coord, heights[row][col]];
-
-                if row == CHUNK_MAP_ROWS || col == CHUNK_MAP_COLS || row == 0 || col == 0 {
-                    normals[row][col] = [0.0, 0.0, 1.0];
-                } else {
-                    let dzdx =
-                        (heights[row][col + 1] - heights[row][col - 1]) / (2.0 * CHUNK_TILE_SIZE);
-                    let dzdy =
-                        (heights[row + 1][col] - heights[row - 1][col]) / (2.0 * CHUNK_TILE_SIZE);
-                    normals[row][col] = cgmath::Vector3::new(-dzdx, -dzdy, 1.0).normalize().into();
-                }
+                positions[row][col] = [
+                    current_col_coord,
+                    current_row_coord,
+                    chunk.edges[(row, col)],
+                ];
+
+                normals[row][col] = chunk.normals[(row, col)];
             }
         }
 
@@ -75,13 +138,12 @@ impl ChunkMapTiles {
                         normals[row][col + 1],
                         normals[row + 1][col + 1],
                     ],
-                    types[row][col],
+                    chunk.tile_types[(row, col)],
                 );
 
                 vertices.extend(tile.vertices);
             }
         }
-        println!("a");
         vertices
     }
 }
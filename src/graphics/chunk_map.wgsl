@@ -13,10 +13,22 @@ struct Light {
 @group(2) @binding(0)
 var<uniform> light: Light;
 
+struct ShadowUniform {
+    light_view_proj: mat4x4<f32>,
+}
+@group(3) @binding(0)
+var<uniform> shadow: ShadowUniform;
+@group(3) @binding(1)
+var t_shadow: texture_depth_2d;
+@group(3) @binding(2)
+var s_shadow: sampler_comparison;
+
 struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) tex_coords: vec2<f32>,
     @location(2) normal: vec3<f32>,
+    @location(3) tangent: vec3<f32>,
+    @location(4) bitangent: vec3<f32>,
 };
 
 struct InstanceInput {
@@ -33,6 +45,8 @@ struct VertexOutput {
     @location(0) tex_coords: vec2<f32>,
     @location(1) world_normal: vec3<f32>,
     @location(2) world_position: vec3<f32>,
+    @location(3) world_tangent: vec3<f32>,
+    @location(4) world_bitangent: vec3<f32>,
 };
 
 @vertex
@@ -52,6 +66,8 @@ fn vs_main(model: VertexInput, instance: InstanceInput) -> VertexOutput {
     var out: VertexOutput;
     out.tex_coords = model.tex_coords;
     out.world_normal = normal_matrix*model.normal;
+    out.world_tangent = normal_matrix * model.tangent;
+    out.world_bitangent = normal_matrix * model.bitangent;
     var world_position: vec4<f32> = model_matrix * vec4<f32>(model.position, 1.0);
     out.world_position = world_position.xyz;
     out.clip_position = camera.view_proj * world_position;
@@ -63,27 +79,63 @@ fn vs_main(model: VertexInput, instance: InstanceInput) -> VertexOutput {
 var t_diffuse: texture_2d<f32>;
 @group(1) @binding(1)
 var s_diffuse: sampler;
+@group(1) @binding(2)
+var t_normal: texture_2d<f32>;
+@group(1) @binding(3)
+var s_normal: sampler;
+
+// 1.0 where the sun reaches `world_position`, 0.0 in full shadow. 3x3 PCF softens the edges,
+// everything outside the shadow map counts as lit
+fn sun_visibility(world_position: vec3<f32>) -> f32 {
+    let light_position = shadow.light_view_proj * vec4<f32>(world_position, 1.0);
+    let ndc = light_position.xyz / light_position.w;
+    if (ndc.x < -1.0 || ndc.x > 1.0 || ndc.y < -1.0 || ndc.y > 1.0 || ndc.z > 1.0) {
+        return 1.0;
+    }
+    let uv = ndc.xy * vec2<f32>(0.5, -0.5) + vec2<f32>(0.5, 0.5);
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_shadow));
+
+    var visibility = 0.0;
+    for (var x = -1; x <= 1; x++) {
+        for (var y = -1; y <= 1; y++) {
+            let offset = vec2<f32>(f32(x), f32(y)) * texel;
+            visibility += textureSampleCompareLevel(t_shadow, s_shadow, uv + offset, ndc.z);
+        }
+    }
+    return visibility / 9.0;
+}
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     let object_color: vec4<f32> = textureSample(t_diffuse, s_diffuse, in.tex_coords);
 
+    // Normal map, from tangent space to world space. Vertices without a tangent frame keep
+    // their normal, as do frames that cancel the mapped normal out
+    let tangent_normal = textureSample(t_normal, s_normal, in.tex_coords).xyz * 2.0 - 1.0;
+    let tangent_matrix = mat3x3<f32>(in.world_tangent, in.world_bitangent, in.world_normal);
+    let mapped_normal = tangent_matrix * tangent_normal;
+    var world_normal = normalize(in.world_normal);
+    if (length(mapped_normal) > 1e-4) {
+        world_normal = normalize(mapped_normal);
+    }
+
     // Ambient light
     let ambient_strength = 0.1;
     let ambient_color = light.color * ambient_strength;
     
     // Direct light
     let light_dir = normalize(light.position - in.world_position);
-    let diffuse_strength = max(dot(in.world_normal, light_dir), 0.0);
+    let diffuse_strength = max(dot(world_normal, light_dir), 0.0);
     let diffuse_color = light.color * diffuse_strength;
     
     // Reflection light
     let view_dir = normalize(camera.view_pos.xyz - in.world_position);
     let half_dir = normalize(view_dir + light_dir);
-    let specular_strength = pow(max(dot(in.world_normal, half_dir), 0.0), 32.0);
+    let specular_strength = pow(max(dot(world_normal, half_dir), 0.0), 32.0);
     let specular_color = specular_strength * light.color;
 
-    let result = (ambient_color + diffuse_color + specular_color) * object_color.xyz;
+    let visibility = sun_visibility(in.world_position);
+    let result = (ambient_color + visibility * (diffuse_color + specular_color)) * object_color.xyz;
 
     return vec4<f32>(result, object_color.a);
 }
@@ -2,19 +2,22 @@ use std::sync;
 use wgpu::util::DeviceExt;
 use winit::window::Window;
 
-use crate::constants::VSYNC;
 use crate::game;
 use crate::graphics::camera::Camera;
+use crate::graphics::capture::FrameCapture;
 use crate::graphics::chunk_map::ChunkMapTiles;
 use crate::graphics::light::Sun;
-use crate::graphics::model::ChunkObject;
+use crate::graphics::model::{ChunkObject, Material};
+use crate::graphics::render_target::RenderTarget;
 use crate::graphics::resources;
+use crate::graphics::shadow::ShadowMap;
 use crate::graphics::texture::Texture;
 use crate::graphics::vertex::{InstanceRaw, MapVertex, ModelVertex, Vertex};
 use crate::graphics::world_map::WorldMapTiles;
 
 use super::texture;
 
+#[allow(non_camel_case_types)]
 #[derive(PartialEq)]
 pub enum GraphicsState {
     WORLD_MAP,
@@ -23,44 +26,39 @@ pub enum GraphicsState {
 
 pub struct Graphics {
     // Generals
-    window: sync::Arc<Window>,
     device: wgpu::Device,
     queue: wgpu::Queue,
-    size: winit::dpi::PhysicalSize<u32>,
-    surface: wgpu::Surface<'static>,
-    swapchain_format: wgpu::TextureFormat,
-    config: wgpu::SurfaceConfiguration,
+    target: RenderTarget,
     // Pipelines
     map_pipeline: wgpu::RenderPipeline,
     chunk_pipeline: wgpu::RenderPipeline,
     light_pipeline: wgpu::RenderPipeline,
     // Textures
     depth_texture: Texture,
-    diffuse_texture: texture::Texture,
+    terrain_material: Material,
     // Objects
     world_map_tiles: WorldMapTiles,
     chunk_map_tiles: ChunkMapTiles,
     sun: Sun,
+    shadow_map: ShadowMap,
     character: ChunkObject,
     // State
     pub state: GraphicsState,
     // Camera
     pub camera: Camera,
+    pub capture: FrameCapture,
     camera_buffer: wgpu::Buffer,
     // ind groups
     camera_bind_group: wgpu::BindGroup,
-    diffuse_bind_group: wgpu::BindGroup,
     hills_bind_group: wgpu::BindGroup,
-    mountains_bind_group: wgpu::BindGroup,
     light_bind_group: wgpu::BindGroup,
 }
 
 impl Graphics {
-    pub async fn new<'a>(
+    pub async fn new(
         window: sync::Arc<Window>,
-        game_for_init: game::game::GameForInit<'a>,
+        game_for_init: game::game::GameForInit,
     ) -> Graphics {
-        // Generals
         let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
         let adapter = instance
             .request_adapter(&wgpu::RequestAdapterOptions::default())
@@ -70,27 +68,53 @@ impl Graphics {
             .request_device(&wgpu::DeviceDescriptor::default(), None)
             .await
             .unwrap();
-        let size = window.inner_size();
         let surface = instance.create_surface(window.clone()).unwrap();
-        let swapchain_cap = surface.get_capabilities(&adapter);
-        let swapchain_format = swapchain_cap.formats[0];
-        let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: swapchain_format,
-            width: size.width,
-            height: size.height,
-            present_mode: match VSYNC {
-                true => wgpu::PresentMode::AutoVsync,
-                false => wgpu::PresentMode::AutoNoVsync,
-            },
-            desired_maximum_frame_latency: 2,
-            alpha_mode: wgpu::CompositeAlphaMode::Auto,
-            view_formats: vec![swapchain_format.add_srgb_suffix()],
+        let target = RenderTarget::window(window, surface, &adapter, &device);
+        Self::with_target(device, queue, target, game_for_init).await
+    }
+
+    /// Renders into a `width` x `height` texture instead of a window, read the frames back
+    /// with `read_image`. Falls back to a software adapter like llvmpipe or lavapipe when
+    /// there is no GPU, `WGPU_BACKEND` picks the backend.
+    pub async fn new_offscreen(
+        width: u32,
+        height: u32,
+        game_for_init: game::game::GameForInit,
+    ) -> anyhow::Result<Graphics> {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::from_env_or_default());
+        let adapter = match instance
+            .request_adapter(&wgpu::RequestAdapterOptions::default())
+            .await
+        {
+            Some(adapter) => adapter,
+            None => instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    force_fallback_adapter: true,
+                    ..Default::default()
+                })
+                .await
+                .ok_or_else(|| anyhow::anyhow!("No graphics adapter available"))?,
         };
-        surface.configure(&device, &config);
+        let (device, queue) = adapter
+            .request_device(&wgpu::DeviceDescriptor::default(), None)
+            .await?;
+        let target = RenderTarget::offscreen(&device, width, height);
+        Ok(Self::with_target(device, queue, target, game_for_init).await)
+    }
+
+    async fn with_target(
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        target: RenderTarget,
+        game_for_init: game::game::GameForInit,
+    ) -> Graphics {
+        // Generals
+        let swapchain_format = target.format();
+        let (width, height) = target.size();
 
         // Camera
-        let camera = Camera::new();
+        let mut camera = Camera::new();
+        camera.aspect = width as f32 / height as f32;
         let camera_uniform = camera.get_camera_uniform();
         let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Camera Buffer"),
@@ -121,12 +145,17 @@ impl Graphics {
         });
 
         // Textures
-        let depth_texture = Texture::create_depth_texture(&device, &config, "depth_texture");
+        let depth_texture = Texture::create_depth_texture(&device, width, height, "depth_texture");
 
-        let diffuse_bytes = include_bytes!("../../res/generated-image.png");
-        let diffuse_texture =
-            texture::Texture::from_bytes(&device, &queue, diffuse_bytes, "generated-image.png")
-                .unwrap();
+        let diffuse_bytes = include_bytes!("../../res/terrain-atlas.png");
+        let diffuse_texture = texture::Texture::from_bytes(
+            &device,
+            &queue,
+            diffuse_bytes,
+            "terrain-atlas.png",
+            false,
+        )
+        .unwrap();
 
         let texture_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -150,29 +179,21 @@ impl Graphics {
                 ],
                 label: Some("texture_bind_group_layout"),
             });
-        let diffuse_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &texture_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-                },
-            ],
-            label: Some("diffuse_bind_group"),
-        });
+
+        // The chunk pipeline draws the terrain and the models, all with a normal map
+        let material_bind_group_layout = Material::bind_group_layout(&device);
+        let terrain_material = Material::new(
+            &device,
+            "terrain_material",
+            &diffuse_texture,
+            &texture::Texture::flat_normal_map(&device, &queue).unwrap(),
+            &material_bind_group_layout,
+        );
 
         // Sposta
         let hills_bytes = include_bytes!("../../res/hills.png");
-        let mountains_bytes = include_bytes!("../../res/mountains.png");
         let hills_texture =
-            texture::Texture::from_bytes(&device, &queue, hills_bytes, "hills.png").unwrap();
-        let mountains_texture =
-            texture::Texture::from_bytes(&device, &queue, mountains_bytes, "mountains.png")
-                .unwrap();
+            texture::Texture::from_bytes(&device, &queue, hills_bytes, "hills.png", false).unwrap();
 
         let hills_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             layout: &texture_bind_group_layout,
@@ -189,21 +210,6 @@ impl Graphics {
             label: Some("diffuse_bind_group"),
         });
 
-        let mountains_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &texture_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&mountains_texture.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&mountains_texture.sampler),
-                },
-            ],
-            label: Some("diffuse_bind_group"),
-        });
-
         // Light
         let sun = Sun::new(&device);
 
@@ -231,6 +237,9 @@ impl Graphics {
             label: Some("Light bind group"),
         });
 
+        // Shadows
+        let shadow_map = ShadowMap::new(&device);
+
         // Pipelines
         let map_pipeline = {
             let map_pipeline_layout =
@@ -260,8 +269,9 @@ impl Graphics {
                     label: None,
                     bind_group_layouts: &[
                         &camera_bind_group_layout,
-                        &texture_bind_group_layout,
+                        &material_bind_group_layout,
                         &light_bind_group_layout,
+                        &shadow_map.bind_group_layout,
                     ],
                     push_constant_ranges: &[],
                 });
@@ -303,43 +313,40 @@ impl Graphics {
         };
 
         // Map initialization
-        let world_map_tiles = WorldMapTiles::new(&device, &game_for_init.world_map);
-        let chunk_map_tiles = ChunkMapTiles::new(&device, &game_for_init.chunk_map);
+        let world_map_tiles = WorldMapTiles::new(&device, &game_for_init.world_map.tiles);
+        let chunk_map_tiles = ChunkMapTiles::new(&device);
 
         let obj_model = resources::load_model(
             "character1.obj",
             &device,
             &queue,
-            &texture_bind_group_layout,
+            &material_bind_group_layout,
         )
         .await
         .unwrap();
         let character = ChunkObject::new(obj_model, &device, &game_for_init.character_pos);
+        let capture = FrameCapture::new(&device);
 
         Graphics {
-            window,
             device,
             queue,
-            size,
-            surface,
-            swapchain_format,
-            config,
+            target,
             map_pipeline,
             chunk_pipeline,
             light_pipeline,
             depth_texture,
-            diffuse_texture,
+            terrain_material,
             world_map_tiles,
             chunk_map_tiles,
             sun,
+            shadow_map,
             character,
             state: GraphicsState::WORLD_MAP,
             camera,
+            capture,
             camera_buffer,
             camera_bind_group,
-            diffuse_bind_group,
             hills_bind_group,
-            mountains_bind_group,
             light_bind_group,
         }
     }
@@ -357,7 +364,7 @@ impl Graphics {
 
         device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             label,
-            layout: Some(&layout),
+            layout: Some(layout),
             vertex: wgpu::VertexState {
                 module: &shader,
                 entry_point: Some("vs_main"),
@@ -393,10 +400,19 @@ impl Graphics {
         positions: &game::game::GameExports,
     ) -> Result<(), wgpu::SurfaceError> {
         self.character.update(&self.queue, &positions.character, 1);
+        self.chunk_map_tiles.sync(&self.device, &positions.chunks);
 
-        self.camera.update(positions.character);
+        // The world map mesh is centered on the origin, chunks are in world space
+        match self.state {
+            GraphicsState::WORLD_MAP => self.camera.update([0.0, 0.0, 0.0]),
+            GraphicsState::CHUNK_MAP => self.camera.update(positions.character),
+        }
 
-        self.sun.update();
+        self.sun.update(positions.character);
+        if let Some(bounds) = self.chunk_map_tiles.bounds() {
+            self.shadow_map
+                .update(&self.queue, self.sun.direction(), bounds);
+        }
 
         let camera_uniform = self.camera.get_camera_uniform();
         self.queue.write_buffer(
@@ -416,25 +432,52 @@ impl Graphics {
 
     pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
         // Generals
-        let frame_texture = self
-            .surface
-            .get_current_texture()
-            .expect("Failed to aquire the next swapchain texture");
-        let texture_view = frame_texture
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor {
-                format: Some(self.swapchain_format.add_srgb_suffix()),
-                ..Default::default()
-            });
+        let frame = self.target.acquire()?;
         let mut encoder = self
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("Render Encoder"),
             });
+
+        // Shadow pre-pass, the depth of the chunks and the character seen from the sun
+        if self.state == GraphicsState::CHUNK_MAP {
+            let mut shadowpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Shadow pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.shadow_map.texture.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            shadowpass.set_pipeline(&self.shadow_map.pipeline);
+            shadowpass.set_bind_group(0, &self.shadow_map.pass_bind_group, &[]);
+            shadowpass.set_vertex_buffer(1, self.chunk_map_tiles.instance_buffer.slice(..));
+            for chunk_mesh in self.chunk_map_tiles.meshes() {
+                shadowpass.set_vertex_buffer(0, chunk_mesh.vertex_buffer.slice(..));
+                shadowpass.draw(0..chunk_mesh.num_vertices, 0..1);
+            }
+            shadowpass.set_vertex_buffer(1, self.character.instance_buffer.slice(..));
+            for mesh in &self.character.model.meshes {
+                shadowpass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+                shadowpass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                shadowpass.draw_indexed(
+                    0..mesh.num_elements,
+                    0,
+                    0..self.character.instances.len() as u32,
+                );
+            }
+        }
+
         let mut renderpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: None,
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &texture_view,
+                view: &frame.view,
                 resolve_target: None,
                 ops: wgpu::Operations {
                     load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
@@ -467,12 +510,16 @@ impl Graphics {
         if self.state == GraphicsState::CHUNK_MAP {
             renderpass.set_pipeline(&self.chunk_pipeline);
             renderpass.set_bind_group(0, &self.camera_bind_group, &[]);
-            renderpass.set_bind_group(1, &self.diffuse_bind_group, &[]);
+            renderpass.set_bind_group(1, &self.terrain_material.bind_group, &[]);
             renderpass.set_bind_group(2, &self.light_bind_group, &[]);
-            renderpass.set_vertex_buffer(0, self.chunk_map_tiles.vertex_buffer.slice(..));
-            renderpass.set_vertex_buffer(1, self.character.instance_buffer.slice(..));
-            renderpass.draw(0..self.chunk_map_tiles.num_vertices, 0..1);
+            renderpass.set_bind_group(3, &self.shadow_map.bind_group, &[]);
+            renderpass.set_vertex_buffer(1, self.chunk_map_tiles.instance_buffer.slice(..));
+            for chunk_mesh in self.chunk_map_tiles.meshes() {
+                renderpass.set_vertex_buffer(0, chunk_mesh.vertex_buffer.slice(..));
+                renderpass.draw(0..chunk_mesh.num_vertices, 0..1);
+            }
             use crate::graphics::resources::CustomDraws;
+            renderpass.set_vertex_buffer(1, self.character.instance_buffer.slice(..));
             renderpass.draw_model_instanced(
                 &self.character.model,
                 0..self.character.instances.len() as u32,
@@ -488,22 +535,42 @@ impl Graphics {
         }
 
         drop(renderpass);
+        self.capture.record(&mut encoder, &frame.texture);
         self.queue.submit([encoder.finish()]);
-        self.window.pre_present_notify();
-        frame_texture.present();
+        self.capture.after_submit();
+        self.target.present(frame);
 
         Ok(())
     }
 
+    /// Stops the sun at `angle` radians over the horizon, for reproducible frames.
+    pub fn fix_sun(&mut self, angle: f32) {
+        self.sun.set_angle(angle);
+        self.sun.frozen = true;
+    }
+
+    /// Last frame rendered offscreen, `None` when drawing to a window.
+    pub fn read_image(&self) -> Option<image::RgbaImage> {
+        self.target.read_image(&self.device, &self.queue)
+    }
+
     pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
         if new_size.width > 0 && new_size.height > 0 {
-            self.size = new_size;
-            self.config.width = new_size.width;
-            self.config.height = new_size.height;
-            self.surface.configure(&self.device, &self.config);
-            self.camera.aspect = self.config.width as f32 / self.config.height as f32;
-            self.depth_texture =
-                Texture::create_depth_texture(&self.device, &self.config, "depth_texture");
+            self.target
+                .resize(&self.device, new_size.width, new_size.height);
+            self.camera.aspect = new_size.width as f32 / new_size.height as f32;
+            self.depth_texture = Texture::create_depth_texture(
+                &self.device,
+                new_size.width,
+                new_size.height,
+                "depth_texture",
+            );
         }
     }
+
+    /// Configures the window surface again at its current size, after it was lost or outdated.
+    pub fn reconfigure(&mut self) {
+        let (width, height) = self.target.size();
+        self.resize(winit::dpi::PhysicalSize::new(width, height));
+    }
 }
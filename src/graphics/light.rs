@@ -1,5 +1,4 @@
 use crate::graphics::model::MapTile;
-use crate::graphics::vertex::MapVertex;
 use wgpu::util::DeviceExt;
 
 #[repr(C)]
@@ -13,8 +12,10 @@ pub struct LightUniform {
 }
 
 pub struct Sun {
-    vertices: Vec<MapVertex>,
     pub num_vertices: usize,
+    radius: f32,
+    /// Keeps the sun at its current angle instead of orbiting.
+    pub frozen: bool,
     pub light_uniform: LightUniform,
     pub vertex_buffer: wgpu::Buffer,
     pub light_buffer: wgpu::Buffer,
@@ -54,20 +55,32 @@ impl Sun {
         });
 
         Self {
-            vertices,
             num_vertices,
+            radius: r,
+            frozen: false,
             light_uniform,
             vertex_buffer,
             light_buffer,
         }
     }
-    pub fn update(&mut self) {
-        let r = f32::sqrt(
-            self.light_uniform.position[0] * self.light_uniform.position[0]
-                + self.light_uniform.position[2] * self.light_uniform.position[2],
-        );
-        self.light_uniform.alpha += 0.001;
-        self.light_uniform.position[0] = r * self.light_uniform.alpha.cos();
-        self.light_uniform.position[2] = r * self.light_uniform.alpha.sin();
+    /// Angle over the horizon in radians, 0.0 rises on +x.
+    pub fn set_angle(&mut self, alpha: f32) {
+        self.light_uniform.alpha = alpha;
+    }
+
+    /// Unit vector from the lit area towards the sun.
+    pub fn direction(&self) -> [f32; 3] {
+        let alpha = self.light_uniform.alpha;
+        [alpha.cos(), 0.0, alpha.sin()]
+    }
+
+    /// Orbits the sun around `center` so the lit area follows the character across chunks.
+    pub fn update(&mut self, center: [f32; 3]) {
+        if !self.frozen {
+            self.light_uniform.alpha += 0.001;
+        }
+        self.light_uniform.position[0] = center[0] + self.radius * self.light_uniform.alpha.cos();
+        self.light_uniform.position[1] = center[1];
+        self.light_uniform.position[2] = center[2] + self.radius * self.light_uniform.alpha.sin();
     }
 }
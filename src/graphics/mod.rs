@@ -1,9 +1,13 @@
 pub mod camera;
+pub mod capture;
 mod chunk_map;
+#[allow(clippy::module_inception)]
 pub mod graphics;
 mod light;
 mod model;
+pub mod render_target;
 mod resources;
+mod shadow;
 mod texture;
 mod vertex;
 mod world_map;
@@ -7,13 +7,10 @@ use wgpu::util::DeviceExt;
 
 // Blender models
 pub struct Material {
-    pub name: String,
-    pub diffuse_texture: texture::Texture,
     pub bind_group: wgpu::BindGroup,
 }
 
 pub struct Mesh {
-    pub name: String,
     pub vertex_buffer: wgpu::Buffer,
     pub index_buffer: wgpu::Buffer,
     pub num_elements: u32,
@@ -25,6 +22,70 @@ pub struct Model {
     pub materials: Vec<Material>,
 }
 
+impl Material {
+    pub fn new(
+        device: &wgpu::Device,
+        name: &str,
+        diffuse_texture: &texture::Texture,
+        normal_texture: &texture::Texture,
+        layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&normal_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(&normal_texture.sampler),
+                },
+            ],
+            label: Some(name),
+        });
+
+        Self { bind_group }
+    }
+
+    /// Diffuse texture and sampler, then normal map and sampler.
+    pub fn bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+        let sampler_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+        };
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                texture_entry(0),
+                sampler_entry(1),
+                texture_entry(2),
+                sampler_entry(3),
+            ],
+            label: Some("material_bind_group_layout"),
+        })
+    }
+}
+
 // Custom models
 
 pub struct MapTile {
@@ -70,45 +131,62 @@ pub struct ChunkTile {
 
 impl ChunkTile {
     pub fn new(positions: [[f32; 3]; 4], normals: [[f32; 3]; 4], tile_type: ChunkTileType) -> Self {
-        let mut tex_coords_multiplier = 0.0;
-        if tile_type == ChunkTileType::GRASS {
-            tex_coords_multiplier = 1.0;
-        }
-        let vertices = vec![
-            TexVertex {
-                position: positions[0],
-                tex_coords: [tex_coords_multiplier * 0.5, 0.0],
-                normal: normals[0],
-            },
-            TexVertex {
-                position: positions[2],
-                tex_coords: [tex_coords_multiplier * 0.5, 0.5],
-                normal: normals[2],
-            },
-            TexVertex {
-                position: positions[3],
-                tex_coords: [0.5 * (tex_coords_multiplier + 1.0), 0.5],
-                normal: normals[3],
-            },
-            TexVertex {
-                position: positions[3],
-                tex_coords: [0.5 * (tex_coords_multiplier + 1.0), 0.5],
-                normal: normals[3],
-            },
-            TexVertex {
-                position: positions[1],
-                tex_coords: [0.5 * (tex_coords_multiplier + 1.0), 0.0],
-                normal: normals[1],
-            },
+        let [u0, v0, u1, v1] = Self::atlas_cell(tile_type);
+        let vertex = |corner: usize, tex_coords: [f32; 2]| {
+            let (tangent, bitangent) = Self::tangent_frame(normals[corner]);
             TexVertex {
-                position: positions[0],
-                tex_coords: [tex_coords_multiplier * 0.5, 0.0],
-                normal: normals[0],
-            },
+                position: positions[corner],
+                tex_coords,
+                normal: normals[corner],
+                tangent,
+                bitangent,
+            }
+        };
+        let vertices = vec![
+            vertex(0, [u0, v0]),
+            vertex(2, [u0, v1]),
+            vertex(3, [u1, v1]),
+            vertex(3, [u1, v1]),
+            vertex(1, [u1, v0]),
+            vertex(0, [u0, v0]),
         ];
 
         Self { vertices }
     }
+
+    // u grows with the rows (+y) and v with the columns (+x), the bitangent points to the top
+    // of the texture like for the loaded models. A normal along ±y has no row direction left,
+    // the columns are used instead
+    fn tangent_frame(normal: [f32; 3]) -> ([f32; 3], [f32; 3]) {
+        let normal = Vector3::from(normal);
+        let up = match normal.y.abs() > 0.999 {
+            false => Vector3::unit_y(),
+            true => Vector3::unit_x(),
+        };
+        let tangent = (up - normal * normal.dot(up)).normalize();
+        (tangent.into(), normal.cross(tangent).into())
+    }
+
+    // [u0, v0, u1, v1] of the type in res/terrain-atlas.png, a 4x2 grid of textures
+    fn atlas_cell(tile_type: ChunkTileType) -> [f32; 4] {
+        let (col, row) = match tile_type {
+            ChunkTileType::DEEP_WATER => (0, 0),
+            ChunkTileType::SHALLOW_WATER => (1, 0),
+            ChunkTileType::SAND => (2, 0),
+            ChunkTileType::DIRT => (3, 0),
+            ChunkTileType::GRASS => (0, 1),
+            ChunkTileType::FOREST_FLOOR => (1, 1),
+            ChunkTileType::ROCK => (2, 1),
+            ChunkTileType::SNOW => (3, 1),
+        };
+        let (width, height) = (1.0 / 4.0, 1.0 / 2.0);
+        [
+            col as f32 * width,
+            row as f32 * height,
+            (col + 1) as f32 * width,
+            (row + 1) as f32 * height,
+        ]
+    }
 }
 
 // High level objects
@@ -118,6 +196,22 @@ pub struct Instance {
 }
 
 impl Instance {
+    pub fn identity() -> Self {
+        Self {
+            position: cgmath::Vector3::zero(),
+            rotation: cgmath::Quaternion::one(),
+        }
+    }
+
+    /// Vertex buffer holding just this instance.
+    pub fn create_buffer(&self, device: &wgpu::Device) -> wgpu::Buffer {
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Instance Buffer"),
+            contents: bytemuck::cast_slice(&[self.to_raw()]),
+            usage: wgpu::BufferUsages::VERTEX,
+        })
+    }
+
     fn to_raw(&self) -> InstanceRaw {
         let model =
             cgmath::Matrix4::from_translation(self.position) * cgmath::Matrix4::from(self.rotation);
@@ -179,3 +273,26 @@ impl ChunkObject {
         );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tangent_frame_is_orthonormal_for_any_normal() {
+        let normals = [
+            Vector3::new(0.0, 0.0, 1.0),
+            Vector3::new(0.0, 1.0, 0.0),
+            Vector3::new(0.0, -1.0, 0.0),
+            Vector3::new(0.2, -0.5, 0.8).normalize(),
+        ];
+        for normal in normals {
+            let (tangent, bitangent) = ChunkTile::tangent_frame(normal.into());
+            let (tangent, bitangent) = (Vector3::from(tangent), Vector3::from(bitangent));
+            assert!((tangent.magnitude() - 1.0).abs() < 1e-5, "{normal:?}");
+            assert!((bitangent.magnitude() - 1.0).abs() < 1e-5, "{normal:?}");
+            assert!(tangent.dot(normal).abs() < 1e-5, "{normal:?}");
+            assert!(bitangent.dot(normal).abs() < 1e-5, "{normal:?}");
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use std::sync;
+use winit::window::Window;
+
+use crate::constants::VSYNC;
+
+/// Where `Graphics` draws: the swapchain of a window, or an offscreen texture that can be
+/// read back, to render on machines without a display.
+pub enum RenderTarget {
+    WINDOW {
+        window: sync::Arc<Window>,
+        surface: wgpu::Surface<'static>,
+        config: wgpu::SurfaceConfiguration,
+    },
+    OFFSCREEN {
+        texture: wgpu::Texture,
+    },
+}
+
+/// The texture a frame is drawn into, handed back to `present` once it's submitted.
+pub struct TargetFrame {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    surface_texture: Option<wgpu::SurfaceTexture>,
+}
+
+impl RenderTarget {
+    pub const OFFSCREEN_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    pub fn window(
+        window: sync::Arc<Window>,
+        surface: wgpu::Surface<'static>,
+        adapter: &wgpu::Adapter,
+        device: &wgpu::Device,
+    ) -> Self {
+        let size = window.inner_size();
+        let swapchain_cap = surface.get_capabilities(adapter);
+        let swapchain_format = swapchain_cap.formats[0];
+        // Copying frames out is needed for screenshots, not every surface allows it
+        let usage = wgpu::TextureUsages::RENDER_ATTACHMENT
+            | (swapchain_cap.usages & wgpu::TextureUsages::COPY_SRC);
+        let config = wgpu::SurfaceConfiguration {
+            usage,
+            format: swapchain_format,
+            width: size.width.max(1),
+            height: size.height.max(1),
+            present_mode: match VSYNC {
+                true => wgpu::PresentMode::AutoVsync,
+                false => wgpu::PresentMode::AutoNoVsync,
+            },
+            desired_maximum_frame_latency: 2,
+            alpha_mode: wgpu::CompositeAlphaMode::Auto,
+            view_formats: vec![swapchain_format.add_srgb_suffix()],
+        };
+        surface.configure(device, &config);
+        Self::WINDOW {
+            window,
+            surface,
+            config,
+        }
+    }
+
+    pub fn offscreen(device: &wgpu::Device, width: u32, height: u32) -> Self {
+        Self::OFFSCREEN {
+            texture: Self::create_offscreen_texture(device, width, height),
+        }
+    }
+
+    fn create_offscreen_texture(device: &wgpu::Device, width: u32, height: u32) -> wgpu::Texture {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Offscreen target"),
+            size: wgpu::Extent3d {
+                width: width.max(1),
+                height: height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::OFFSCREEN_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        })
+    }
+
+    /// Format the pipelines render to.
+    pub fn format(&self) -> wgpu::TextureFormat {
+        match self {
+            Self::WINDOW { config, .. } => config.format,
+            Self::OFFSCREEN { texture } => texture.format(),
+        }
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        match self {
+            Self::WINDOW { config, .. } => (config.width, config.height),
+            Self::OFFSCREEN { texture } => (texture.width(), texture.height()),
+        }
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        match self {
+            Self::WINDOW {
+                surface, config, ..
+            } => {
+                config.width = width;
+                config.height = height;
+                surface.configure(device, config);
+            }
+            Self::OFFSCREEN { texture } => {
+                *texture = Self::create_offscreen_texture(device, width, height);
+            }
+        }
+    }
+
+    /// Next texture to draw into. A window can fail to give one, e.g. when its surface is
+    /// lost or outdated after a resize.
+    pub fn acquire(&self) -> Result<TargetFrame, wgpu::SurfaceError> {
+        match self {
+            Self::WINDOW {
+                surface, config, ..
+            } => {
+                let surface_texture = surface.get_current_texture()?;
+                let view = surface_texture
+                    .texture
+                    .create_view(&wgpu::TextureViewDescriptor {
+                        format: Some(config.format.add_srgb_suffix()),
+                        ..Default::default()
+                    });
+                Ok(TargetFrame {
+                    texture: surface_texture.texture.clone(),
+                    view,
+                    surface_texture: Some(surface_texture),
+                })
+            }
+            Self::OFFSCREEN { texture } => Ok(TargetFrame {
+                texture: texture.clone(),
+                view: texture.create_view(&wgpu::TextureViewDescriptor::default()),
+                surface_texture: None,
+            }),
+        }
+    }
+
+    /// Shows a submitted frame in the window, offscreen frames just stay in the texture.
+    pub fn present(&self, frame: TargetFrame) {
+        if let (Self::WINDOW { window, .. }, Some(surface_texture)) = (self, frame.surface_texture)
+        {
+            window.pre_present_notify();
+            surface_texture.present();
+        }
+    }
+
+    /// Copies the offscreen texture back to the CPU, `None` for a window.
+    pub fn read_image(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Option<image::RgbaImage> {
+        match self {
+            Self::WINDOW { .. } => None,
+            Self::OFFSCREEN { texture } => Some(read_texture(device, queue, texture)),
+        }
+    }
+}
+
+/// Reads back an RGBA8 or BGRA8 texture with `COPY_SRC` usage, blocking until the GPU is done.
+pub fn read_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+) -> image::RgbaImage {
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Readback Encoder"),
+    });
+    let buffer = copy_to_buffer(device, &mut encoder, texture);
+    queue.submit([encoder.finish()]);
+
+    let slice = buffer.slice(..);
+    slice.map_async(wgpu::MapMode::Read, |result| result.unwrap());
+    device.poll(wgpu::Maintain::Wait);
+    let image = buffer_to_image(
+        &slice.get_mapped_range(),
+        texture.width(),
+        texture.height(),
+        texture.format(),
+    );
+    buffer.unmap();
+    image
+}
+
+/// Records a copy of `texture` into a new buffer that can be mapped for reading once the
+/// commands are submitted. Rows are padded, see `buffer_to_image`.
+pub fn copy_to_buffer(
+    device: &wgpu::Device,
+    encoder: &mut wgpu::CommandEncoder,
+    texture: &wgpu::Texture,
+) -> wgpu::Buffer {
+    let buffer = create_readback_buffer(device, texture.width(), texture.height());
+    copy_into_buffer(encoder, texture, &buffer);
+    buffer
+}
+
+/// Mappable buffer big enough for a padded copy of a `width` x `height` texture.
+pub fn create_readback_buffer(device: &wgpu::Device, width: u32, height: u32) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Readback buffer"),
+        size: padded_row_bytes(width) as u64 * height as u64,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    })
+}
+
+/// Records a copy of `texture` into `buffer`, which has to be at least as big as a
+/// `create_readback_buffer` of the same size and unmapped.
+pub fn copy_into_buffer(
+    encoder: &mut wgpu::CommandEncoder,
+    texture: &wgpu::Texture,
+    buffer: &wgpu::Buffer,
+) {
+    encoder.copy_texture_to_buffer(
+        texture.as_image_copy(),
+        wgpu::TexelCopyBufferInfo {
+            buffer,
+            layout: wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_row_bytes(texture.width())),
+                rows_per_image: Some(texture.height()),
+            },
+        },
+        texture.size(),
+    );
+}
+
+/// Image out of the bytes `copy_to_buffer` wrote, dropping the row padding.
+pub fn buffer_to_image(
+    padded: &[u8],
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+) -> image::RgbaImage {
+    let row_bytes = width as usize * 4;
+    let mut pixels = Vec::with_capacity(row_bytes * height as usize);
+    for row in padded.chunks(padded_row_bytes(width) as usize) {
+        pixels.extend_from_slice(&row[..row_bytes]);
+    }
+
+    let mut image = image::RgbaImage::from_raw(width, height, pixels).unwrap();
+    // Swapchains are often BGRA
+    if matches!(
+        format,
+        wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+    ) {
+        image.pixels_mut().for_each(|pixel| pixel.0.swap(0, 2));
+    }
+    image
+}
+
+// Buffer rows of a texture copy have to be aligned
+fn padded_row_bytes(width: u32) -> u32 {
+    (width * 4).div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT) * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buffer_rows_lose_their_padding() {
+        let (width, height) = (3, 2);
+        let padded_row = padded_row_bytes(width) as usize;
+        assert_eq!(padded_row, 256);
+        // Pixel (x, y) is [x, y, 10, 255], the padding is 0xAA
+        let mut padded = vec![0xAA; padded_row * height as usize];
+        for y in 0..height as usize {
+            for x in 0..width as usize {
+                let start = y * padded_row + x * 4;
+                padded[start..start + 4].copy_from_slice(&[x as u8, y as u8, 10, 255]);
+            }
+        }
+
+        let image = buffer_to_image(&padded, width, height, wgpu::TextureFormat::Rgba8UnormSrgb);
+        assert_eq!(image.dimensions(), (width, height));
+        assert_eq!(image.get_pixel(2, 1).0, [2, 1, 10, 255]);
+        assert!(image.pixels().all(|pixel| pixel.0[2] == 10));
+
+        let image = buffer_to_image(&padded, width, height, wgpu::TextureFormat::Bgra8Unorm);
+        assert_eq!(image.get_pixel(2, 1).0, [10, 1, 2, 255]);
+    }
+}
@@ -1,6 +1,7 @@
 use crate::graphics::model::{Material, Mesh, Model};
 use crate::graphics::vertex::ModelVertex;
 use crate::graphics::{model, texture};
+use cgmath::{InnerSpace, Vector2, Vector3, Zero};
 use std::io::{BufReader, Cursor};
 use std::ops::Range;
 use wgpu::util::DeviceExt;
@@ -28,6 +29,7 @@ pub async fn load_binary(file_name: &str) -> anyhow::Result<Vec<u8>> {
 
 pub async fn load_texture(
     file_name: &str,
+    is_normal_map: bool,
     device: &wgpu::Device,
     queue: &wgpu::Queue,
 ) -> anyhow::Result<texture::Texture> {
@@ -35,7 +37,7 @@ pub async fn load_texture(
         println!("Failed to load texture binary: {}", e);
         e
     })?;
-    texture::Texture::from_bytes(device, queue, &data, file_name)
+    texture::Texture::from_bytes(device, queue, &data, file_name, is_normal_map)
 }
 
 pub async fn load_model(
@@ -64,45 +66,41 @@ pub async fn load_model(
         },
     )
     .await
-    .map_err(|e| {
+    .inspect_err(|e| {
         println!("Failed to load object: {}", e);
-        e
     })?;
 
     let mut materials = Vec::new();
     for m in obj_materials? {
-        let diffuse_texture = load_texture(&m.diffuse_texture, device, queue)
+        let diffuse_texture = load_texture(&m.diffuse_texture, false, device, queue)
             .await
             .map_err(|e| {
                 println!("Failed to load object texture: {}", e);
                 e
             })?;
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        let normal_texture = match normal_map_file(&m) {
+            Some(normal_file) => load_texture(normal_file, true, device, queue)
+                .await
+                .map_err(|e| {
+                    println!("Failed to load object normal map: {}", e);
+                    e
+                })?,
+            None => texture::Texture::flat_normal_map(device, queue)?,
+        };
+
+        materials.push(model::Material::new(
+            device,
+            &m.name,
+            &diffuse_texture,
+            &normal_texture,
             layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-                },
-            ],
-            label: None,
-        });
-
-        materials.push(model::Material {
-            name: m.name,
-            diffuse_texture,
-            bind_group,
-        })
+        ))
     }
 
     let meshes = models
         .into_iter()
         .map(|m| {
-            let vertices = (0..m.mesh.positions.len() / 3)
+            let mut vertices = (0..m.mesh.positions.len() / 3)
                 .map(|i| {
                     if m.mesh.normals.is_empty() {
                         ModelVertex {
@@ -116,6 +114,8 @@ pub async fn load_model(
                                 1.0 - m.mesh.texcoords[i * 2 + 1],
                             ],
                             normal: [0.0, 0.0, 0.0],
+                            tangent: [0.0, 0.0, 0.0],
+                            bitangent: [0.0, 0.0, 0.0],
                         }
                     } else {
                         ModelVertex {
@@ -133,10 +133,13 @@ pub async fn load_model(
                                 m.mesh.normals[i * 3 + 1],
                                 m.mesh.normals[i * 3 + 2],
                             ],
+                            tangent: [0.0, 0.0, 0.0],
+                            bitangent: [0.0, 0.0, 0.0],
                         }
                     }
                 })
                 .collect::<Vec<_>>();
+            compute_tangents(&mut vertices, &m.mesh.indices);
 
             let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: Some(&format!("{:?} Vertex Buffer", file_name)),
@@ -150,7 +153,6 @@ pub async fn load_model(
             });
 
             model::Mesh {
-                name: file_name.to_string(),
                 vertex_buffer,
                 index_buffer,
                 num_elements: m.mesh.indices.len() as u32,
@@ -162,15 +164,68 @@ pub async fn load_model(
     Ok(model::Model { meshes, materials })
 }
 
+// MTL normal map, `map_Bump`/`bump` or `norm`. Options like `-bm 0.5` come before the file
+fn normal_map_file(material: &tobj::Material) -> Option<&str> {
+    let entry = match material.normal_texture.is_empty() {
+        false => Some(material.normal_texture.as_str()),
+        true => material.unknown_param.get("norm").map(String::as_str),
+    };
+    entry.and_then(|entry| entry.split_whitespace().last())
+}
+
+// Tangent and bitangent of each vertex, averaged over the triangles sharing it. Triangles
+// without a usable uv mapping are skipped, leaving vertices with no tangent frame only the
+// normal
+fn compute_tangents(vertices: &mut [ModelVertex], indices: &[u32]) {
+    let mut tangents = vec![Vector3::zero(); vertices.len()];
+    let mut bitangents = vec![Vector3::zero(); vertices.len()];
+
+    for triangle in indices.chunks_exact(3) {
+        let [v0, v1, v2] = [0, 1, 2].map(|corner| vertices[triangle[corner] as usize]);
+        let [p0, p1, p2] = [v0, v1, v2].map(|v| Vector3::from(v.position));
+        let [uv0, uv1, uv2] = [v0, v1, v2].map(|v| Vector2::from(v.tex_coords));
+
+        let (delta_pos1, delta_pos2) = (p1 - p0, p2 - p0);
+        let (delta_uv1, delta_uv2) = (uv1 - uv0, uv2 - uv0);
+        let determinant = delta_uv1.x * delta_uv2.y - delta_uv1.y * delta_uv2.x;
+        if determinant.abs() < f32::EPSILON {
+            continue;
+        }
+        let r = 1.0 / determinant;
+        let tangent = (delta_pos1 * delta_uv2.y - delta_pos2 * delta_uv1.y) * r;
+        // v was flipped on load, flipping the bitangent back keeps it pointing to the top of
+        // the texture as normal maps expect
+        let bitangent = (delta_pos2 * delta_uv1.x - delta_pos1 * delta_uv2.x) * -r;
+
+        for &index in triangle {
+            tangents[index as usize] += tangent;
+            bitangents[index as usize] += bitangent;
+        }
+    }
+
+    for (vertex, (tangent, bitangent)) in vertices
+        .iter_mut()
+        .zip(tangents.into_iter().zip(bitangents))
+    {
+        // Gram-Schmidt, averaging leaves the frame skewed against the normal
+        let normal = Vector3::from(vertex.normal);
+        let tangent = tangent - normal * normal.dot(tangent);
+        if tangent.magnitude2() < f32::EPSILON {
+            continue;
+        }
+        let tangent = tangent.normalize();
+        let bitangent =
+            bitangent - normal * normal.dot(bitangent) - tangent * tangent.dot(bitangent);
+        if bitangent.magnitude2() < f32::EPSILON {
+            continue;
+        }
+        vertex.tangent = tangent.into();
+        vertex.bitangent = bitangent.normalize().into();
+    }
+}
+
 // Draws
 pub trait CustomDraws<'a> {
-    fn draw_mesh(
-        &mut self,
-        mesh: &'a Mesh,
-        material: &'a Material,
-        camera_bind_group: &'a wgpu::BindGroup,
-        light_bind_group: &'a wgpu::BindGroup,
-    );
     fn draw_mesh_instanced(
         &mut self,
         mesh: &'a Mesh,
@@ -179,12 +234,6 @@ pub trait CustomDraws<'a> {
         camera_bind_group: &'a wgpu::BindGroup,
         light_bind_group: &'a wgpu::BindGroup,
     );
-    fn draw_model(
-        &mut self,
-        model: &'a Model,
-        camera_bind_group: &'a wgpu::BindGroup,
-        light_bind_group: &'a wgpu::BindGroup,
-    );
     fn draw_model_instanced(
         &mut self,
         model: &'a Model,
@@ -198,16 +247,6 @@ impl<'a, 'b> CustomDraws<'b> for wgpu::RenderPass<'a>
 where
     'b: 'a,
 {
-    fn draw_mesh(
-        &mut self,
-        mesh: &'b Mesh,
-        material: &'b Material,
-        camera_bind_group: &'b wgpu::BindGroup,
-        light_bind_group: &'a wgpu::BindGroup,
-    ) {
-        self.draw_mesh_instanced(mesh, material, 0..1, camera_bind_group, light_bind_group);
-    }
-
     fn draw_mesh_instanced(
         &mut self,
         mesh: &'b Mesh,
@@ -224,15 +263,6 @@ where
         self.draw_indexed(0..mesh.num_elements, 0, instances);
     }
 
-    fn draw_model(
-        &mut self,
-        model: &'b Model,
-        camera_bind_group: &'b wgpu::BindGroup,
-        light_bind_group: &'a wgpu::BindGroup,
-    ) {
-        self.draw_model_instanced(model, 0..1, camera_bind_group, light_bind_group);
-    }
-
     fn draw_model_instanced(
         &mut self,
         model: &'b Model,
@@ -252,3 +282,76 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vertex(position: [f32; 3], tex_coords: [f32; 2], normal: [f32; 3]) -> ModelVertex {
+        ModelVertex {
+            position,
+            tex_coords,
+            normal,
+            tangent: [0.0, 0.0, 0.0],
+            bitangent: [0.0, 0.0, 0.0],
+        }
+    }
+
+    fn assert_close(actual: [f32; 3], expected: [f32; 3]) {
+        let difference = Vector3::from(actual) - Vector3::from(expected);
+        assert!(difference.magnitude() < 1e-5, "{actual:?} != {expected:?}");
+    }
+
+    #[test]
+    fn quad_tangents_follow_the_uv_mapping() {
+        // Unit quad in the xy plane, u along +x and v (flipped on load) along -y
+        let normal = [0.0, 0.0, 1.0];
+        let mut vertices = vec![
+            vertex([0.0, 0.0, 0.0], [0.0, 1.0], normal),
+            vertex([1.0, 0.0, 0.0], [1.0, 1.0], normal),
+            vertex([1.0, 1.0, 0.0], [1.0, 0.0], normal),
+            vertex([0.0, 1.0, 0.0], [0.0, 0.0], normal),
+        ];
+        compute_tangents(&mut vertices, &[0, 1, 2, 2, 3, 0]);
+
+        for vertex in &vertices {
+            assert_close(vertex.tangent, [1.0, 0.0, 0.0]);
+            assert_close(vertex.bitangent, [0.0, 1.0, 0.0]);
+        }
+    }
+
+    #[test]
+    fn tangents_are_orthogonalized_against_the_normal() {
+        // Same quad with tilted normals, the frame has to stay orthonormal
+        let normal = Vector3::new(0.3, -0.2, 1.0).normalize();
+        let mut vertices = vec![
+            vertex([0.0, 0.0, 0.0], [0.0, 1.0], normal.into()),
+            vertex([1.0, 0.0, 0.0], [1.0, 1.0], normal.into()),
+            vertex([1.0, 1.0, 0.0], [1.0, 0.0], normal.into()),
+            vertex([0.0, 1.0, 0.0], [0.0, 0.0], normal.into()),
+        ];
+        compute_tangents(&mut vertices, &[0, 1, 2, 2, 3, 0]);
+
+        for vertex in &vertices {
+            let tangent = Vector3::from(vertex.tangent);
+            let bitangent = Vector3::from(vertex.bitangent);
+            assert!((tangent.magnitude() - 1.0).abs() < 1e-5);
+            assert!((bitangent.magnitude() - 1.0).abs() < 1e-5);
+            assert!(tangent.dot(normal).abs() < 1e-5);
+            assert!(bitangent.dot(normal).abs() < 1e-5);
+            assert!(tangent.dot(bitangent).abs() < 1e-5);
+            // Still pointing along +u and +y
+            assert!(tangent.x > 0.9 && bitangent.y > 0.9);
+        }
+    }
+
+    #[test]
+    fn normal_map_options_are_skipped() {
+        let mtl =
+            "newmtl bumped\nmap_Bump -bm 0.5 file.png\n\nnewmtl norm\nnorm -bm 0.5 other.png\n";
+        let (materials, _) = tobj::load_mtl_buf(&mut mtl.as_bytes()).unwrap();
+
+        assert_eq!(normal_map_file(&materials[0]), Some("file.png"));
+        assert_eq!(normal_map_file(&materials[1]), Some("other.png"));
+    }
+}
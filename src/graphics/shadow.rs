@@ -0,0 +1,209 @@
+use cgmath::{Array, EuclideanSpace, Matrix4, Point3, SquareMatrix, Transform, Vector3};
+use wgpu::util::DeviceExt;
+
+use crate::constants::{
+    SHADOW_BIAS_CONSTANT, SHADOW_BIAS_SLOPE, SHADOW_BOUNDS_MARGIN, SHADOW_MAP_SIZE,
+};
+use crate::graphics::texture::Texture;
+use crate::graphics::vertex::{InstanceRaw, ModelVertex, Vertex};
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ShadowUniform {
+    light_view_proj: [[f32; 4]; 4],
+}
+
+/// Depth of the scene seen from the sun, rendered before the frame and sampled by the chunk
+/// shader to darken what the sun can't reach.
+pub struct ShadowMap {
+    pub texture: Texture,
+    pub pipeline: wgpu::RenderPipeline,
+    uniform_buffer: wgpu::Buffer,
+    /// Light matrix only, for the depth pre-pass.
+    pub pass_bind_group: wgpu::BindGroup,
+    /// Light matrix, shadow map and comparison sampler, for the lit pipelines.
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl ShadowMap {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let texture =
+            Texture::create_depth_texture(device, SHADOW_MAP_SIZE, SHADOW_MAP_SIZE, "shadow_map");
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Shadow uniform buffer"),
+            contents: bytemuck::cast_slice(&[ShadowUniform {
+                light_view_proj: Matrix4::<f32>::identity().into(),
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let uniform_entry = wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let pass_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[uniform_entry],
+                label: Some("Shadow pass bind group layout"),
+            });
+        let pass_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &pass_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+            label: Some("Shadow pass bind group"),
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                uniform_entry,
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Depth,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                    count: None,
+                },
+            ],
+            label: Some("Shadow bind group layout"),
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                },
+            ],
+            label: Some("Shadow bind group"),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Shadow pipeline layout"),
+            bind_group_layouts: &[&pass_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Shadow shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shadow.wgsl").into()),
+        });
+        // Depth only, the bias keeps lit surfaces from shadowing themselves
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Shadow render pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[ModelVertex::desc(), InstanceRaw::desc()],
+                compilation_options: Default::default(),
+            },
+            fragment: None,
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState {
+                    constant: SHADOW_BIAS_CONSTANT,
+                    slope_scale: SHADOW_BIAS_SLOPE,
+                    clamp: 0.0,
+                },
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        Self {
+            texture,
+            pipeline,
+            uniform_buffer,
+            pass_bind_group,
+            bind_group_layout,
+            bind_group,
+        }
+    }
+
+    /// Points the orthographic light frustum along `sun_direction` (towards the sun) and
+    /// fits it around `bounds`, the min and max corners of what has to cast and receive
+    /// shadows.
+    pub fn update(&self, queue: &wgpu::Queue, sun_direction: [f32; 3], bounds: [[f32; 3]; 2]) {
+        let margin = Vector3::from_value(SHADOW_BOUNDS_MARGIN);
+        let (min, max) = (
+            Point3::from(bounds[0]) - margin,
+            Point3::from(bounds[1]) + margin,
+        );
+        let center = min.midpoint(max);
+        // The sun orbits in the xz plane, so y is never parallel to it
+        let view = Matrix4::look_at_rh(
+            center + Vector3::from(sun_direction),
+            center,
+            Vector3::unit_y(),
+        );
+
+        let mut light_min = Point3::from_value(f32::INFINITY);
+        let mut light_max = Point3::from_value(f32::NEG_INFINITY);
+        for i in 0..8 {
+            let corner = Point3::new(
+                if i & 1 == 0 { min.x } else { max.x },
+                if i & 2 == 0 { min.y } else { max.y },
+                if i & 4 == 0 { min.z } else { max.z },
+            );
+            let corner = view.transform_point(corner);
+            light_min = Point3::new(
+                light_min.x.min(corner.x),
+                light_min.y.min(corner.y),
+                light_min.z.min(corner.z),
+            );
+            light_max = Point3::new(
+                light_max.x.max(corner.x),
+                light_max.y.max(corner.y),
+                light_max.z.max(corner.z),
+            );
+        }
+        // The view looks down -z, near and far are distances in front of it. `cgmath::ortho`
+        // maps depth to -1..1 and wgpu wants 0..1
+        let depth_to_wgpu = Matrix4::from_translation(Vector3::new(0.0, 0.0, 0.5))
+            * Matrix4::from_nonuniform_scale(1.0, 1.0, 0.5);
+        let proj = depth_to_wgpu
+            * cgmath::ortho(
+                light_min.x,
+                light_max.x,
+                light_min.y,
+                light_max.y,
+                -light_max.z,
+                -light_min.z,
+            );
+
+        let uniform = ShadowUniform {
+            light_view_proj: (proj * view).into(),
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+}
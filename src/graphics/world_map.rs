@@ -1,21 +1,18 @@
-use crate::constants::{TILE_SIZE, WORLD_MAP_COLS, WORLD_MAP_ROWS};
-use crate::game::world_map::{Tile, TileType};
+use crate::constants::TILE_SIZE;
+use crate::game::grid::Grid;
+use crate::game::world_map::Tile;
 use crate::graphics::model::MapTile;
 use crate::graphics::vertex::MapVertex;
 use wgpu::util::DeviceExt;
 
 pub struct WorldMapTiles {
-    pub vertices: Vec<MapVertex>,
     pub num_vertices: usize,
     pub vertex_buffer: wgpu::Buffer,
 }
 
 impl WorldMapTiles {
-    pub fn new(
-        device: &wgpu::Device,
-        world_tiles: &[[Tile; WORLD_MAP_COLS]; WORLD_MAP_ROWS],
-    ) -> Self {
-        let vertices = Self::initialize_map_vertices(WORLD_MAP_ROWS, WORLD_MAP_COLS, world_tiles);
+    pub fn new(device: &wgpu::Device, world_tiles: &Grid<Tile>) -> Self {
+        let vertices = Self::initialize_map_vertices(world_tiles);
         let num_vertices = vertices.len();
 
         let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -25,23 +22,20 @@ impl WorldMapTiles {
         });
 
         Self {
-            vertices,
             num_vertices,
             vertex_buffer,
         }
     }
 
-    fn initialize_map_vertices(
-        rows: usize,
-        cols: usize,
-        world_tiles: &[[Tile; WORLD_MAP_COLS]; WORLD_MAP_ROWS],
-    ) -> Vec<MapVertex> {
+    fn initialize_map_vertices(world_tiles: &Grid<Tile>) -> Vec<MapVertex> {
         let mut vertices = Vec::new();
+        let rows = world_tiles.height();
+        let cols = world_tiles.width();
         let start_row = -1.0 + (2.0 - TILE_SIZE * rows as f32) / 2.0;
         let start_col = -1.0 + (2.0 - TILE_SIZE * cols as f32) / 2.0;
         for row in 0..rows {
             for col in 0..cols {
-                //let pos_h2 = match world_tiles[row][col].tile_type {
+                //let pos_h2 = match world_tiles[(row, col)].tile_type {
                 //    TileType::WATER => TILE_SIZE * 1.0,
                 //    TileType::GRASS => TILE_SIZE * 1.5,
                 //    TileType::WOODS => TILE_SIZE * 2.0,
@@ -0,0 +1,142 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+
+use crate::constants;
+use crate::game::character::CharacterMovement;
+use crate::game::game::{Game, GameState};
+
+/// A scripted input: from `tick` on the character moves along `direction`, or stands still
+/// if it is `[0.0, 0.0]`.
+struct ScriptStep {
+    tick: u64,
+    direction: [f32; 2],
+}
+
+pub struct HeadlessOptions {
+    pub ticks: u64,
+    pub script: Option<String>,
+    pub dump: Option<String>,
+    pub save: Option<String>,
+}
+
+/// Runs the game without window or GPU for `ticks` fixed ticks, feeding it the scripted
+/// input, then prints the final state or writes it to the dump file, and saves the game if
+/// asked to.
+///
+/// Chunk generation is waited for after every tick, so the same seed and script always
+/// give the same state.
+pub fn run(mut game: Game, options: HeadlessOptions) -> io::Result<()> {
+    let script = match &options.script {
+        Some(path) => parse_script(&fs::read_to_string(path)?)?,
+        None => Vec::new(),
+    };
+    let dt = constants::THICK_RATE_MILLIS as f32 / 1000.0;
+    let mut character_movement = CharacterMovement::new();
+    let mut next_step = 0;
+
+    game.finish_loading();
+    for tick in 0..options.ticks {
+        while next_step < script.len() && script[next_step].tick <= tick {
+            character_movement = movement(script[next_step].direction);
+            next_step += 1;
+        }
+        if game.update(character_movement, dt) == GameState::DEAD {
+            break;
+        }
+        game.finish_loading();
+    }
+
+    if let Some(path) = &options.save {
+        game.save(path)?;
+    }
+
+    let state = dump_state(&game);
+    match &options.dump {
+        Some(path) => fs::write(path, state),
+        None => {
+            print!("{}", state);
+            Ok(())
+        }
+    }
+}
+
+// One "<tick> <dx> <dy>" step per line, sorted by tick. Empty lines and lines starting
+// with '#' are skipped
+fn parse_script(text: &str) -> io::Result<Vec<ScriptStep>> {
+    let mut steps = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("script line {}: expected \"<tick> <dx> <dy>\"", number + 1),
+            )
+        };
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [tick, dx, dy] = fields[..] else {
+            return Err(invalid());
+        };
+        steps.push(ScriptStep {
+            tick: tick.parse().map_err(|_| invalid())?,
+            direction: [
+                dx.parse().map_err(|_| invalid())?,
+                dy.parse().map_err(|_| invalid())?,
+            ],
+        });
+    }
+    steps.sort_by_key(|step| step.tick);
+    Ok(steps)
+}
+
+fn movement(direction: [f32; 2]) -> CharacterMovement {
+    let [dx, dy] = direction;
+    let length = f32::sqrt(dx * dx + dy * dy);
+    let mut character_movement = CharacterMovement::new();
+    if length > 0.0 {
+        character_movement.moving = true;
+        character_movement.direction = [dx / length, dy / length];
+    }
+    character_movement
+}
+
+fn dump_state(game: &Game) -> String {
+    let exports = game.get_exports();
+    let world_map = game.get_for_init().world_map;
+    let [x, y, z] = exports.character;
+    let mut state = String::new();
+
+    writeln!(state, "tick {}", exports.tick).unwrap();
+    writeln!(state, "character {:.3} {:.3} {:.3}", x, y, z).unwrap();
+
+    let mut chunks: Vec<_> = exports.chunks.iter().map(|(coord, _)| *coord).collect();
+    chunks.sort_by_key(|coord| (coord.row, coord.col));
+    write!(state, "chunks {}", chunks.len()).unwrap();
+    for coord in chunks {
+        write!(state, " ({},{})", coord.row, coord.col).unwrap();
+    }
+    writeln!(state).unwrap();
+
+    let mut tile_counts = BTreeMap::new();
+    for tile in world_map.tiles.iter() {
+        *tile_counts
+            .entry(format!("{:?}", tile.tile_type))
+            .or_insert(0) += 1;
+    }
+    write!(
+        state,
+        "world {}x{}",
+        world_map.tiles.width(),
+        world_map.tiles.height()
+    )
+    .unwrap();
+    for (tile_type, count) in tile_counts {
+        write!(state, " {}={}", tile_type, count).unwrap();
+    }
+    writeln!(state).unwrap();
+    state
+}
@@ -3,37 +3,21 @@ use std::collections::HashSet;
 use winit::event::ElementState;
 use winit::keyboard::KeyCode;
 
-use crate::GraphicsState;
+use crate::game::character::CharacterMovement;
 use crate::graphics::camera::Camera;
+use crate::graphics::capture::FrameCapture;
+use crate::graphics::graphics::GraphicsState;
 
+#[derive(Default)]
 pub struct InputState {
     current: HashSet<KeyCode>,
     previous: HashSet<KeyCode>,
     character_movement: CharacterMovement,
 }
 
-#[derive(Clone, Copy)]
-pub struct CharacterMovement {
-    pub moving: bool,
-    pub direction: [f32; 2],
-}
-
-impl CharacterMovement {
-    pub fn new() -> Self {
-        Self {
-            moving: false,
-            direction: [0.0, 0.0],
-        }
-    }
-}
-
 impl InputState {
     pub fn new() -> Self {
-        Self {
-            current: HashSet::new(),
-            previous: HashSet::new(),
-            character_movement: CharacterMovement::new(),
-        }
+        Self::default()
     }
 
     pub fn get_character_movement(&self) -> CharacterMovement {
@@ -67,14 +51,11 @@ impl InputState {
         self.current.contains(&key) && !self.previous.contains(&key)
     }
 
-    fn is_released(&self, key: KeyCode) -> bool {
-        !self.current.contains(&key) && self.previous.contains(&key)
-    }
-
     pub fn do_your_job(
         &mut self,
         graphics_state: &mut GraphicsState,
         camera: &mut Camera,
+        capture: &mut FrameCapture,
         running: &mut bool,
     ) {
         // Others
@@ -90,6 +71,14 @@ impl InputState {
             *running = false;
         }
 
+        // Capture: F12 saves a screenshot, F11 starts and stops an image sequence
+        if self.is_just_pressed(KeyCode::F12) {
+            capture.request_screenshot();
+        }
+        if self.is_just_pressed(KeyCode::F11) {
+            capture.toggle_burst();
+        }
+
         // Character movement
         let mut character_movement_straight = 0;
         let mut character_movement_lateral = 0;
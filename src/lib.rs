@@ -0,0 +1,14 @@
+pub mod constants;
+pub mod export;
+pub mod game;
+pub mod headless;
+pub mod triple_buffer;
+
+#[cfg(feature = "graphics")]
+pub mod app;
+#[cfg(feature = "graphics")]
+pub mod graphics;
+#[cfg(feature = "graphics")]
+pub mod input;
+#[cfg(feature = "graphics")]
+pub mod offscreen;
@@ -1,226 +1,176 @@
-mod constants;
-mod game;
-mod graphics;
-mod input;
-
-use std::{
-    sync::{Arc, Mutex},
-    thread, time,
-};
-
-use input::{CharacterMovement, InputState};
-use winit::{
-    application::ApplicationHandler,
-    event::{KeyEvent, WindowEvent},
-    event_loop::{ActiveEventLoop, EventLoop},
-    keyboard::PhysicalKey,
-    window::{Window, WindowId},
-};
-
-use game::game::{Game, GameExports, GameState};
-use graphics::graphics::{Graphics, GraphicsState};
-
-struct Aiomad {
-    graphics: Option<Arc<Mutex<Graphics>>>,
-    draw_thread: Option<thread::JoinHandle<()>>,
-    game: Arc<Mutex<Game>>,
-    game_thread: Option<thread::JoinHandle<()>>,
-    input_state: Arc<Mutex<input::InputState>>,
-    game_exports: Arc<Mutex<game::game::GameExports>>,
-}
+use airomad2::constants;
+use airomad2::export;
+use airomad2::game::chunk_manager::ChunkCoord;
+use airomad2::game::game::Game;
+use airomad2::game::seed::WorldSeed;
+use airomad2::headless;
 
 fn main() {
     env_logger::init();
-    let event_loop = EventLoop::new().unwrap();
-    event_loop.set_control_flow(winit::event_loop::ControlFlow::Poll);
-
-    let game = Game::new();
-    let init_game_exports = game.get_exports();
 
-    let mut aiomad = Aiomad {
-        graphics: None,
-        draw_thread: None,
-        game: Arc::new(Mutex::new(game)),
-        game_thread: None,
-        input_state: Arc::new(Mutex::new(InputState::new())),
-        game_exports: Arc::new(Mutex::new(init_game_exports)),
-    };
-
-    aiomad.game_thread = Some(thread::spawn({
-        let game = Arc::clone(&aiomad.game);
-        let game_exports = Arc::clone(&aiomad.game_exports);
-        let input_state = Arc::clone(&aiomad.input_state);
-
-        move || {
-            let mut running = true;
-            let mut delta_time = time::Instant::now();
-            while running {
-                let mut character_movement = CharacterMovement::new();
-
-                if let Ok(input_state) = input_state.lock() {
-                    character_movement = input_state.get_character_movement();
-                }
-                if let Ok(mut game) = game.lock() {
-                    if game.update(character_movement) == GameState::DEAD {
-                        running = false;
-                    };
-                    if let Ok(mut game_exports) = game_exports.lock() {
-                        *game_exports = game.get_exports();
-                    }
-                }
-
-                if delta_time.elapsed() <= time::Duration::from_millis(constants::THICK_RATE_MILLIS)
-                {
-                    thread::sleep(
-                        time::Duration::from_millis(constants::THICK_RATE_MILLIS)
-                            - delta_time.elapsed(),
-                    );
-                }
-                delta_time = time::Instant::now();
+    let args: Vec<String> = std::env::args().collect();
+    let game = match arg_value(&args, "--load") {
+        Some(path) => match Game::load(path) {
+            Ok(game) => {
+                eprintln!("Loaded {}", path);
+                game
             }
+            Err(error) => {
+                eprintln!("Loading {} failed: {}", path, error);
+                std::process::exit(1);
+            }
+        },
+        None => {
+            let seed = seed_from_args(&args);
+            let (world_width, world_height) = size_from_args(
+                &args,
+                "--world-size",
+                (
+                    constants::DEFAULT_WORLD_MAP_WIDTH,
+                    constants::DEFAULT_WORLD_MAP_HEIGHT,
+                ),
+                constants::MIN_WORLD_MAP_SIZE,
+            );
+            eprintln!(
+                "World seed: {}, size: {}x{}",
+                seed.0, world_width, world_height
+            );
+            Game::new(seed, world_width, world_height)
         }
-    }));
-
-    event_loop.run_app(&mut aiomad).unwrap();
-}
-
-impl Aiomad {
-    fn terminate(&mut self, event_loop: &ActiveEventLoop) {
-        println!("Terminating...");
-
-        if let Some(thread) = self.draw_thread.take() {
-            thread.join().unwrap();
-            println!("Thread draw_thread catched");
-        }
-        if let Ok(mut game) = self.game.lock() {
-            game.terminate();
-            println!("Game terminated");
-        }
-        if let Some(thread) = self.game_thread.take() {
-            thread.join().unwrap();
-            println!("Thread game_thread catched");
+    };
+    // Where the game is saved when it stops, e.g. "--save world.sav"
+    let save = arg_value(&args, "--save").map(String::from);
+
+    // e.g. "export --seed 5 --png world.png --ansi --heightmap chunk.png --chunk 32,32"
+    if args.get(1).is_some_and(|arg| arg == "export") {
+        let options = export::ExportOptions {
+            png: arg_value(&args, "--png").map(String::from),
+            ansi: args.iter().any(|arg| arg == "--ansi"),
+            ascii: args.iter().any(|arg| arg == "--ascii"),
+            heightmap: arg_value(&args, "--heightmap").map(String::from),
+            chunk: chunk_from_args(&args),
+        };
+        if let Err(error) = export::run(&game.save_data(), options) {
+            eprintln!("Export failed: {}", error);
+            std::process::exit(1);
         }
-        if let Some(graphics) = self.graphics.take() {
-            drop(graphics);
-            println!("Graphics dropped to help Mr. SegFault");
+        return;
+    }
+
+    // e.g. "render --seed 5 --out frame.png --size 640x480 --view chunk"
+    #[cfg(feature = "graphics")]
+    if args.get(1).is_some_and(|arg| arg == "render") {
+        use airomad2::graphics::graphics::GraphicsState;
+        let mut game = game;
+        game.finish_loading();
+        let (width, height) = size_from_args(
+            &args,
+            "--size",
+            (
+                constants::DEFAULT_RENDER_WIDTH,
+                constants::DEFAULT_RENDER_HEIGHT,
+            ),
+            1,
+        );
+        let options = airomad2::offscreen::RenderOptions {
+            path: arg_value(&args, "--out").unwrap_or("frame.png").to_string(),
+            width: width as u32,
+            height: height as u32,
+            view: match arg_value(&args, "--view") {
+                Some("chunk") => GraphicsState::CHUNK_MAP,
+                _ => GraphicsState::WORLD_MAP,
+            },
+        };
+        if let Err(error) = airomad2::offscreen::run(&game, options) {
+            eprintln!("Render failed: {}", error);
+            std::process::exit(1);
         }
-        println!("Exit called from thread: {:?}", std::thread::current().id());
-        event_loop.exit();
+        return;
     }
 
-    fn frontend_loop(&mut self) {
-        let graphics = Arc::clone(self.graphics.as_ref().unwrap());
-        let game_exports = Arc::clone(&self.game_exports);
-        let input_state = Arc::clone(&self.input_state);
+    // e.g. "--headless --ticks 1000 --input walk.txt --dump state.txt"
+    if args.iter().any(|arg| arg == "--headless") {
+        let options = headless::HeadlessOptions {
+            ticks: ticks_from_args(&args),
+            script: arg_value(&args, "--input").map(String::from),
+            dump: arg_value(&args, "--dump").map(String::from),
+            save,
+        };
+        if let Err(error) = headless::run(game, options) {
+            eprintln!("Headless run failed: {}", error);
+            std::process::exit(1);
+        }
+        return;
+    }
 
-        self.draw_thread = Some(thread::spawn({
-            move || {
-                let mut running = true;
-                let mut frame_count = 0;
-                let mut fps_timer = time::Instant::now();
-                let mut delta_time = time::Instant::now();
-                let mut game_exports_copy = GameExports::new();
-                while running {
-                    if let Ok(mut graphics) = graphics.lock() {
-                        if let Ok(game_exports) = game_exports.lock() {
-                            game_exports_copy = game_exports.clone();
-                        }
-                        Self::redraw(&game_exports_copy, &mut graphics);
+    #[cfg(feature = "graphics")]
+    airomad2::app::run(game, save);
+    #[cfg(not(feature = "graphics"))]
+    eprintln!("Built without the graphics feature, only --headless is available");
+}
 
-                        if let Ok(mut input_state) = input_state.lock() {
-                            let (mut state, mut camera) = {
-                                let graphics_ref = &mut *graphics;
-                                (&mut graphics_ref.state, &mut graphics_ref.camera)
-                            };
-                            input_state.do_your_job(&mut state, &mut camera, &mut running);
-                        }
-                    }
+fn arg_value<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|arg| arg == name)
+        .and_then(|i| args.get(i + 1))
+        .map(|value| value.as_str())
+}
 
-                    if fps_timer.elapsed() >= time::Duration::from_secs(1) {
-                        println!("FPS: {}", frame_count);
-                        frame_count = 0;
-                        fps_timer = time::Instant::now();
-                    }
-                    if delta_time.elapsed()
-                        <= time::Duration::from_millis(constants::MAX_FRAMERATE_MILLIS)
-                    {
-                        thread::sleep(
-                            time::Duration::from_millis(constants::MAX_FRAMERATE_MILLIS)
-                                - delta_time.elapsed(),
-                        );
-                    }
-                    delta_time = time::Instant::now();
-                    frame_count += 1;
-                }
-            }
-        }));
+fn seed_from_args(args: &[String]) -> WorldSeed {
+    match arg_value(args, "--seed").map(|seed| seed.parse::<u64>()) {
+        Some(Ok(seed)) => WorldSeed::new(seed),
+        Some(Err(_)) => {
+            eprintln!("Invalid --seed value, using a random one");
+            WorldSeed::random()
+        }
+        None => WorldSeed::random(),
     }
+}
 
-    fn redraw(positions: &game::game::GameExports, graphics: &mut Graphics) {
-        match graphics.update_all(&positions) {
-            Ok(_) => {}
-            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {}
-            Err(wgpu::SurfaceError::OutOfMemory | wgpu::SurfaceError::Other) => {
-                println!("OutOfMemory");
-            }
-            Err(wgpu::SurfaceError::Timeout) => {
-                println!("Surface timeout")
-            }
-        };
+fn ticks_from_args(args: &[String]) -> u64 {
+    match arg_value(args, "--ticks").map(|ticks| ticks.parse::<u64>()) {
+        Some(Ok(ticks)) => ticks,
+        Some(Err(_)) => {
+            eprintln!("Invalid --ticks value, using the default one");
+            constants::DEFAULT_HEADLESS_TICKS
+        }
+        None => constants::DEFAULT_HEADLESS_TICKS,
     }
 }
 
-impl ApplicationHandler for Aiomad {
-    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
-        let window = Arc::new(
-            event_loop
-                .create_window(Window::default_attributes().with_title("Airomad"))
-                .unwrap(),
-        );
-        if let Ok(game) = self.game.lock() {
-            let graphics = pollster::block_on(Graphics::new(window.clone(), game.get_for_init()));
-            self.graphics = Some(Arc::new(Mutex::new(graphics)));
+// Expects "<row>,<col>", e.g. "--chunk 32,32"
+fn chunk_from_args(args: &[String]) -> Option<ChunkCoord> {
+    let chunk = arg_value(args, "--chunk")?;
+    match chunk
+        .split_once(',')
+        .map(|(row, col)| (row.parse::<i32>(), col.parse::<i32>()))
+    {
+        Some((Ok(row), Ok(col))) => Some(ChunkCoord::new(row, col)),
+        _ => {
+            eprintln!("Invalid --chunk value, using the chunk under the character");
+            None
         }
-
-        self.frontend_loop();
     }
+}
 
-    fn window_event(
-        &mut self,
-        event_loop: &ActiveEventLoop,
-        _window_id: WindowId,
-        event: WindowEvent,
-    ) {
-        match event {
-            WindowEvent::CloseRequested => self.terminate(event_loop),
-
-            WindowEvent::RedrawRequested => {
-                println!("I DONT CARE ABOUT YOUR STUPID REDRAWREQUEST");
-            }
-            WindowEvent::KeyboardInput {
-                event:
-                    KeyEvent {
-                        physical_key: PhysicalKey::Code(key),
-                        state,
-                        repeat,
-                        ..
-                    },
-                ..
-            } => {
-                if let Ok(mut input_state) = self.input_state.lock() {
-                    input_state.update_key(key, state, repeat);
-                }
-                if key == winit::keyboard::KeyCode::Escape {
-                    self.terminate(event_loop);
-                }
-            }
-
-            WindowEvent::Resized(size) => {
-                if let Ok(mut graphics) = self.graphics.as_ref().unwrap().lock() {
-                    graphics.resize(size);
-                }
-            }
-            _ => (),
+// Expects "<width>x<height>", e.g. "--world-size 129x65"
+fn size_from_args(
+    args: &[String],
+    name: &str,
+    default_size: (usize, usize),
+    min: usize,
+) -> (usize, usize) {
+    let Some(size) = arg_value(args, name) else {
+        return default_size;
+    };
+    match size
+        .split_once('x')
+        .map(|(width, height)| (width.parse::<usize>(), height.parse::<usize>()))
+    {
+        Some((Ok(width), Ok(height))) if width >= min && height >= min => (width, height),
+        _ => {
+            eprintln!("Invalid {} value, using the default one", name);
+            default_size
         }
     }
 }
@@ -0,0 +1,27 @@
+use crate::game::game::Game;
+use crate::graphics::graphics::{Graphics, GraphicsState};
+
+pub struct RenderOptions {
+    pub path: String,
+    pub width: u32,
+    pub height: u32,
+    pub view: GraphicsState,
+}
+
+/// Renders the current state of the game once, without a window, and writes it as a PNG.
+/// Works on machines without a display or GPU as long as a software adapter is installed.
+pub fn run(game: &Game, options: RenderOptions) -> anyhow::Result<()> {
+    let mut graphics = pollster::block_on(Graphics::new_offscreen(
+        options.width,
+        options.height,
+        game.get_for_init(),
+    ))?;
+    graphics.state = options.view;
+    graphics.update_all(&game.get_exports())?;
+    let image = graphics
+        .read_image()
+        .ok_or_else(|| anyhow::anyhow!("Offscreen graphics gave no image"))?;
+    image.save(&options.path)?;
+    eprintln!("Frame written to {}", options.path);
+    Ok(())
+}
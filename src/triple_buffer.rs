@@ -0,0 +1,172 @@
+#[cfg(loom)]
+use loom::cell::UnsafeCell;
+#[cfg(loom)]
+use loom::sync::Arc;
+#[cfg(loom)]
+use loom::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(not(loom))]
+use std::sync::Arc;
+#[cfg(not(loom))]
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// The shared slot index lives in the low bits, FRESH tells that the writer published into it
+// since the reader last looked
+const INDEX_MASK: usize = 0b11;
+const FRESH: usize = 0b100;
+
+/// Lock-free single producer, single consumer exchange of the latest value.
+///
+/// Three slots: the writer owns one, the reader owns one and the third is shared. Publishing
+/// swaps the writer slot with the shared one, reading swaps the shared slot with the reader
+/// one if it holds something new. Neither side ever waits for the other, and a slow reader
+/// just skips the values it missed.
+struct TripleBuffer<T> {
+    slots: [UnsafeCell<T>; 3],
+    shared: AtomicUsize,
+}
+
+// Every slot is only ever accessed by the side that currently owns its index, and ownership
+// moves through the atomic swaps on `shared`
+unsafe impl<T: Send> Sync for TripleBuffer<T> {}
+
+pub struct Writer<T> {
+    buffer: Arc<TripleBuffer<T>>,
+    index: usize,
+}
+
+pub struct Reader<T> {
+    buffer: Arc<TripleBuffer<T>>,
+    index: usize,
+}
+
+/// Creates the two ends of a triple buffer holding `initial` until the first publish.
+pub fn triple_buffer<T: Clone + Send>(initial: T) -> (Writer<T>, Reader<T>) {
+    let buffer = Arc::new(TripleBuffer {
+        slots: [
+            UnsafeCell::new(initial.clone()),
+            UnsafeCell::new(initial.clone()),
+            UnsafeCell::new(initial),
+        ],
+        shared: AtomicUsize::new(1),
+    });
+    (
+        Writer {
+            buffer: Arc::clone(&buffer),
+            index: 0,
+        },
+        Reader { buffer, index: 2 },
+    )
+}
+
+impl<T> Writer<T> {
+    pub fn publish(&mut self, value: T) {
+        // SAFETY: the writer slot isn't reachable by the reader until the swap below
+        self.buffer.slots[self.index].with_mut(|slot| unsafe { *slot = value });
+        let previous = self
+            .buffer
+            .shared
+            .swap(self.index | FRESH, Ordering::AcqRel);
+        self.index = previous & INDEX_MASK;
+    }
+}
+
+impl<T> Reader<T> {
+    /// Most recently published value, or the same one as last time if nothing new came.
+    pub fn latest(&mut self) -> &T {
+        if self.buffer.shared.load(Ordering::Relaxed) & FRESH != 0 {
+            let previous = self.buffer.shared.swap(self.index, Ordering::AcqRel);
+            self.index = previous & INDEX_MASK;
+        }
+        // SAFETY: the reader slot isn't reachable by the writer until the next swap, which
+        // needs `&mut self` and so ends this borrow first
+        self.buffer.slots[self.index].with(|slot| unsafe { &*slot })
+    }
+}
+
+// The closure based access of loom's cell, which checks every access against the others
+#[cfg(not(loom))]
+struct UnsafeCell<T>(std::cell::UnsafeCell<T>);
+
+#[cfg(not(loom))]
+impl<T> UnsafeCell<T> {
+    fn new(value: T) -> Self {
+        Self(std::cell::UnsafeCell::new(value))
+    }
+
+    fn with<R>(&self, f: impl FnOnce(*const T) -> R) -> R {
+        f(self.0.get())
+    }
+
+    fn with_mut<R>(&self, f: impl FnOnce(*mut T) -> R) -> R {
+        f(self.0.get())
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reader_sees_the_initial_value_then_the_latest_one() {
+        let (mut writer, mut reader) = triple_buffer(0);
+        assert_eq!(*reader.latest(), 0);
+
+        writer.publish(1);
+        writer.publish(2);
+        writer.publish(3);
+        assert_eq!(*reader.latest(), 3);
+        // Nothing new, the same value again
+        assert_eq!(*reader.latest(), 3);
+
+        writer.publish(4);
+        assert_eq!(*reader.latest(), 4);
+    }
+
+    #[test]
+    fn reads_are_never_torn_under_contention() {
+        const PUBLISHES: u64 = 200_000;
+        let (mut writer, mut reader) = triple_buffer([0u64; 32]);
+
+        let producer = std::thread::spawn(move || {
+            for value in 1..=PUBLISHES {
+                writer.publish([value; 32]);
+            }
+        });
+
+        // A slot read while the writer fills it would mix two values
+        let mut last = 0;
+        while last < PUBLISHES {
+            let values = *reader.latest();
+            assert!(values.iter().all(|&value| value == values[0]), "torn read");
+            assert!(values[0] >= last, "went back from {last} to {}", values[0]);
+            last = values[0];
+        }
+        producer.join().unwrap();
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use super::*;
+
+    #[test]
+    fn loom_reader_and_writer_never_share_a_slot() {
+        loom::model(|| {
+            let (mut writer, mut reader) = triple_buffer(0);
+            let producer = loom::thread::spawn(move || {
+                for value in 1..=3 {
+                    writer.publish(value);
+                }
+            });
+
+            let mut last = 0;
+            for _ in 0..3 {
+                let value = *reader.latest();
+                assert!(value >= last);
+                last = value;
+            }
+            producer.join().unwrap();
+            assert_eq!(*reader.latest(), 3);
+        });
+    }
+}
@@ -0,0 +1,137 @@
+//! Golden-image tests: fixed-seed scenes rendered offscreen and compared against the PNGs in
+//! `tests/golden/`. Differences go to `target/golden-diff/`.
+//!
+//! Regenerate the references after an intended visual change with
+//! `UPDATE_GOLDEN=1 cargo test --test golden`. They need a graphics adapter, a software one
+//! like llvmpipe or lavapipe is enough. On machines without any, `GOLDEN_ALLOW_SKIP=1` skips
+//! them instead of failing.
+
+use std::path::{Path, PathBuf};
+
+use airomad2::game::game::Game;
+use airomad2::game::seed::WorldSeed;
+use airomad2::graphics::graphics::{Graphics, GraphicsState};
+use image::{Rgba, RgbaImage};
+
+const WIDTH: u32 = 320;
+const HEIGHT: u32 = 240;
+const SEED: u64 = 2;
+const WORLD_SIZE: usize = 33;
+const SUN_ANGLE: f32 = 1.0;
+
+// Colour distance above which a pixel counts as different, on the 0..=765 "redmean" scale
+const PIXEL_TOLERANCE: f32 = 24.0;
+// Share of different pixels allowed, absorbs rasterization differences between adapters
+const MAX_DIFFERENT_PIXELS: f32 = 0.005;
+
+#[test]
+fn world_map_matches_golden() {
+    check_scene("world_map", GraphicsState::WORLD_MAP, [0.0, -4.0, 5.0]);
+}
+
+#[test]
+fn chunk_map_matches_golden() {
+    check_scene("chunk_map", GraphicsState::CHUNK_MAP, [30.0, 15.0, 25.0]);
+}
+
+fn check_scene(name: &str, state: GraphicsState, camera_offset: [f32; 3]) {
+    let mut game = Game::new(WorldSeed::new(SEED), WORLD_SIZE, WORLD_SIZE);
+    game.finish_loading();
+    let exports = game.get_exports();
+
+    let mut graphics =
+        match pollster::block_on(Graphics::new_offscreen(WIDTH, HEIGHT, game.get_for_init())) {
+            Ok(graphics) => graphics,
+            Err(error) if std::env::var_os("GOLDEN_ALLOW_SKIP").is_some() => {
+                println!("Skipping golden image {}: {}", name, error);
+                return;
+            }
+            Err(error) => panic!(
+                "No graphics adapter for golden image {}: {}, set GOLDEN_ALLOW_SKIP=1 to skip",
+                name, error
+            ),
+        };
+    let target = match state {
+        GraphicsState::WORLD_MAP => [0.0, 0.0, 0.0],
+        GraphicsState::CHUNK_MAP => exports.character,
+    };
+    graphics.state = state;
+    graphics.camera.place(target, camera_offset);
+    graphics.fix_sun(SUN_ANGLE);
+    graphics.update_all(&exports).unwrap();
+    let actual = graphics.read_image().unwrap();
+
+    let golden_path = golden_dir().join(format!("{}.png", name));
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        actual.save(&golden_path).unwrap();
+        println!("Updated {}", golden_path.display());
+        return;
+    }
+    let Ok(golden) = image::open(&golden_path) else {
+        panic!(
+            "Missing {}, create it with UPDATE_GOLDEN=1",
+            golden_path.display()
+        );
+    };
+    let golden = golden.to_rgba8();
+    assert_eq!(
+        golden.dimensions(),
+        actual.dimensions(),
+        "{} has the wrong size",
+        golden_path.display()
+    );
+
+    let (diff, different) = diff_images(&golden, &actual);
+    let share = different as f32 / (WIDTH * HEIGHT) as f32;
+    if share > MAX_DIFFERENT_PIXELS {
+        let diff_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("target/golden-diff");
+        std::fs::create_dir_all(&diff_dir).unwrap();
+        actual
+            .save(diff_dir.join(format!("{}-actual.png", name)))
+            .unwrap();
+        diff.save(diff_dir.join(format!("{}-diff.png", name)))
+            .unwrap();
+        panic!(
+            "{}: {:.2}% of the pixels differ from the golden image, see {}",
+            name,
+            share * 100.0,
+            diff_dir.display()
+        );
+    }
+}
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+// Dimmed grayscale copy of the golden image with the different pixels in red, and how many
+// there are
+fn diff_images(golden: &RgbaImage, actual: &RgbaImage) -> (RgbaImage, usize) {
+    let mut different = 0;
+    let diff = RgbaImage::from_fn(golden.width(), golden.height(), |x, y| {
+        let (expected, got) = (golden.get_pixel(x, y), actual.get_pixel(x, y));
+        if color_distance(expected, got) > PIXEL_TOLERANCE {
+            different += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            let [r, g, b, _] = expected.0.map(|channel| channel as f32);
+            let gray = ((0.299 * r + 0.587 * g + 0.114 * b) * 0.4) as u8;
+            Rgba([gray, gray, gray, 255])
+        }
+    });
+    (diff, different)
+}
+
+// "Redmean" approximation of the perceived distance between two colours, cheap and closer
+// to what the eye sees than a plain euclidean RGB distance
+fn color_distance(a: &Rgba<u8>, b: &Rgba<u8>) -> f32 {
+    let [ar, ag, ab, _] = a.0.map(|channel| channel as f32);
+    let [br, bg, bb, _] = b.0.map(|channel| channel as f32);
+    let mean_red = (ar + br) / 2.0;
+    let (dr, dg, db) = (ar - br, ag - bg, ab - bb);
+    f32::sqrt(
+        (2.0 + mean_red / 256.0) * dr * dr
+            + 4.0 * dg * dg
+            + (2.0 + (255.0 - mean_red) / 256.0) * db * db,
+    )
+}
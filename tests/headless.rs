@@ -0,0 +1,41 @@
+//! Headless runs of the binary: the state printed on stdout has to be the same for the same
+//! seed and script, so that two runs can be diffed.
+
+use std::path::Path;
+use std::process::Command;
+
+const SCRIPT: &str = "\
+# tick dx dy
+0 1 0
+60 1 1
+120 0 -1
+160 0 0
+";
+
+fn headless_run(script: &Path) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_airomad2"))
+        .args(["--headless", "--seed", "2", "--world-size", "33x33"])
+        .args(["--ticks", "200", "--input"])
+        .arg(script)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn same_seed_and_script_print_the_same_state() {
+    let script = std::env::temp_dir().join(format!("airomad2-walk-{}.txt", std::process::id()));
+    std::fs::write(&script, SCRIPT).unwrap();
+
+    let first = headless_run(&script);
+    let second = headless_run(&script);
+    std::fs::remove_file(&script).unwrap();
+
+    // Only the dump, status lines go to stderr
+    assert!(first.starts_with("tick 200\ncharacter "), "{first}");
+    assert_eq!(first.lines().count(), 4, "{first}");
+    // The character starts at the center of the world, the script walks it away from there
+    assert!(!first.contains("character 1650.000 1650.000"), "{first}");
+    assert_eq!(first, second);
+}